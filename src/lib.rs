@@ -0,0 +1,14 @@
+extern crate core;
+#[cfg(test)]
+extern crate serde_json;
+
+pub mod nes;
+#[cfg(test)]
+mod tests;
+
+pub use nes::console::Nes;
+pub use nes::cpu::Cpu;
+pub use nes::error::{Error, Result};
+pub use nes::mapper::Mapper;
+pub use nes::memory::{FlatMemory, Memory, NesMemory};
+pub use nes::rom::Rom;
@@ -1,40 +1,162 @@
+extern crate ctrlc;
+extern crate nes_rs;
 extern crate time;
-extern crate core;
 
-#[allow(dead_code, unused_assignments, unused_mut)]
-mod nes;
-mod tests;
-
-use nes::cpu::Cpu;
-use nes::rom::Rom;
+use nes_rs::nes::rom::Timing;
+use nes_rs::nes::trace::Tracer;
+use nes_rs::{Error, Nes, Result, Rom};
 
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fs::File;
-use std::boxed::Box;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// seconds per NTSC frame
+const FRAME_TIME: f64 = 1.0 / 60.0988;
+/// seconds per PAL and Dendy frame
+const PAL_FRAME_TIME: f64 = 1.0 / 50.0070;
+/// frames between flushes of battery-backed RAM to the save file, about 5 seconds
+const SAVE_INTERVAL: u32 = 300;
 
 
 /// TODO:
-/// - Test CPU
 /// - Implement PPU
 /// - Implement keyboard input
 /// - Implement APU
 ///
 fn main() {
-    let rom_path = env::args().skip(1).next().expect("Failed to find rom file");
-    let rom = Box::new(Rom::load(&mut File::open(&Path::new(&rom_path)).unwrap()));
+    let mut trace = false;
+    let mut bios_path = None;
+    let mut rom_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--trace" => trace = true,
+            "--bios" => bios_path = args.next().map(PathBuf::from),
+            _ => rom_path = Some(arg),
+        }
+    }
+    let rom_path = rom_path.expect("Failed to find rom file");
+
+    if let Err(err) = run(&rom_path, bios_path, trace) {
+        eprintln!("{}: {}", rom_path, err);
+        process::exit(1);
+    }
+}
+
+fn run(rom_path: &str, bios_path: Option<PathBuf>, trace: bool) -> Result<()> {
+    let rom = match Rom::load(&mut File::open(Path::new(rom_path))?) {
+        Err(Error::MissingBios) => {
+            // disk images run on the FDS BIOS, by default disksys.rom next to them
+            let bios_path = bios_path
+                .unwrap_or_else(|| Path::new(rom_path).with_file_name("disksys.rom"));
+            let mut bios = File::open(bios_path).map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => Error::MissingBios,
+                _ => Error::Io(err),
+            })?;
+            Rom::load_fds(&mut File::open(Path::new(rom_path))?, &mut bios)?
+        }
+        rom => rom?,
+    };
 
     println!("Rom loaded: {}", rom.header);
+    if let Some(ref board) = rom.board {
+        println!("UNIF board: {}", board);
+    }
+
+    let frame_time = match rom.header.timing() {
+        Timing::Pal | Timing::Dendy => PAL_FRAME_TIME,
+        Timing::Ntsc | Timing::MultiRegion => FRAME_TIME,
+    };
 
-    let mut cpu = Cpu::new(rom);
-    println!("{:?}", cpu);
+    let mut nes = Nes::new(rom)?;
+    if trace {
+        nes.cpu.tracer = Some(Tracer::new(io::stdout()));
+    }
 
-    cpu.reset();
+    // battery-backed RAM lives in a .sav file next to the rom
+    let save_path = Path::new(rom_path).with_extension("sav");
+    match File::open(&save_path) {
+        Ok(mut file) => nes.load_save(&mut file)?,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    let mut saved = Vec::new();
+    nes.write_save(&mut saved)?;
 
+    let result = emulate(&mut nes, frame_time, &save_path, &mut saved, watch_quit());
+    flush_save(&nes, &save_path, &mut saved)?;
+    result
+}
+
+/// Returns a flag that is set on Ctrl-C or once a line saying q or quit is typed, there is no
+/// window to close yet
+fn watch_quit() -> Arc<AtomicBool> {
+    let quit = Arc::new(AtomicBool::new(false));
+    let flag = quit.clone();
+    // without the handler Ctrl-C still stops the emulator, only without flushing the save
+    let _ = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed));
+
+    let flag = quit.clone();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(ref line) if line.trim() == "q" || line.trim() == "quit" => {
+                    flag.store(true, Ordering::Relaxed);
+                    return;
+                }
+                Ok(_) => {}
+                Err(_) => return,
+            }
+        }
+    });
+    quit
+}
+
+/// Runs until quit is set or the console stops with an error
+fn emulate(
+    nes: &mut Nes,
+    frame_time: f64,
+    save_path: &Path,
+    saved: &mut Vec<u8>,
+    quit: Arc<AtomicBool>,
+) -> Result<()> {
     let mut last_time = time::precise_time_s();
-    let mut frame = 0;
+    let mut frames = 0u32;
+
+    while !quit.load(Ordering::Relaxed) {
+        nes.run_frame()?;
+
+        frames = (frames + 1) % SAVE_INTERVAL;
+        if frames == 0 {
+            flush_save(nes, save_path, saved)?;
+        }
 
-    loop {
-        cpu.execute_instruction();
+        // there is no video or audio to sync to yet, so just keep to the cartridge's frame rate
+        let elapsed = time::precise_time_s() - last_time;
+        if elapsed < frame_time {
+            thread::sleep(Duration::from_millis(((frame_time - elapsed) * 1000.0) as u64));
+        }
+        last_time = time::precise_time_s();
     }
+
+    Ok(())
+}
+
+/// Writes the save file if battery-backed RAM changed since it was last saved
+fn flush_save(nes: &Nes, save_path: &Path, saved: &mut Vec<u8>) -> Result<()> {
+    let mut save = Vec::new();
+    nes.write_save(&mut save)?;
+    if save != *saved {
+        File::create(save_path)?.write_all(&save)?;
+        *saved = save;
+    }
+
+    Ok(())
 }
@@ -0,0 +1,35 @@
+/// Audio processing unit. A stub for now: it keeps time with the CPU, but register writes are
+/// ignored and nothing is played.
+#[derive(Debug, Default)]
+pub struct Apu {
+    /// CPU cycles since power on
+    pub cycle: u64,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu { cycle: 0 }
+    }
+
+    pub fn power_on(&mut self) {
+        self.cycle = 0;
+    }
+
+    /// The reset button silences every channel, which the stub has nothing to do for
+    pub fn reset(&mut self) {}
+
+    /// Advances by a single CPU cycle
+    pub fn tick(&mut self) {
+        self.cycle += 1;
+    }
+
+    pub fn load(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    pub fn peek(&self, _address: u16) -> u8 {
+        0
+    }
+
+    pub fn store(&mut self, _address: u16, _value: u8) {}
+}
@@ -0,0 +1,473 @@
+//! A small 6502 assembler for writing test programs and synthetic test roms.
+//!
+//! Accepts the usual syntax: one instruction per line, `label:` definitions, `;` comments and
+//! the `.org`, `.byte` and `.word` directives. Values are written as `$ff` (hex), `%1010`
+//! (binary), decimal or a label, optionally followed by `+n`/`-n` and prefixed with `<`/`>` to
+//! take the low/high byte. Zero page addressing is used whenever the operand is known to fit by
+//! the time the instruction is reached, so labels defined further down always assemble as
+//! absolute addresses.
+
+use super::disasm::{AddressingMode, Index, OPCODES};
+use super::memory::Memory;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Result of assembling a source file
+#[derive(Debug)]
+pub struct Program {
+    /// contiguous runs of bytes, one per .org
+    pub segments: Vec<Segment>,
+    pub labels: HashMap<String, u16>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl Program {
+    /// Stores every segment into memory
+    pub fn load<M: Memory>(&self, memory: &mut M) {
+        for segment in &self.segments {
+            for (i, byte) in segment.bytes.iter().enumerate() {
+                memory.store(segment.origin.wrapping_add(i as u16), *byte);
+            }
+        }
+    }
+
+    /// Address of a label, if it was defined
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels.get(name).cloned()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based line the error was found on
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Assembles source into a program
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut lines = Vec::new();
+    for (i, text) in source.lines().enumerate() {
+        let number = i + 1;
+        let error = |message: String| AsmError {
+            line: number,
+            message,
+        };
+        let (labels, statement) = parse_line(text).map_err(error)?;
+        lines.push(Line {
+            number,
+            labels,
+            statement,
+            address: 0,
+            opcode: 0,
+        });
+    }
+
+    let mut labels = HashMap::new();
+    let mut segments = Vec::new();
+
+    // first pass: assign addresses to labels and pick opcodes, which decides instruction sizes
+    let mut pc: u32 = 0;
+    for line in &mut lines {
+        let number = line.number;
+        let error = |message: String| AsmError {
+            line: number,
+            message,
+        };
+
+        if let Statement::Org(ref expr) = line.statement {
+            pc = expr
+                .evaluate(&labels)
+                .ok_or_else(|| error("the origin must be known when it is reached".to_string()))?
+                as u32;
+        }
+
+        for label in &line.labels {
+            if labels.insert(label.clone(), pc as u16).is_some() {
+                return Err(error(format!("label {} defined twice", label)));
+            }
+        }
+
+        line.address = pc as u16;
+        pc += match line.statement {
+            Statement::Org(_) | Statement::Empty => 0,
+            Statement::Bytes(ref values) => values.len() as u32,
+            Statement::Words(ref values) => values.len() as u32 * 2,
+            Statement::Instruction(ref mnemonic, ref operand) => {
+                line.opcode = select_opcode(mnemonic, operand, &labels).map_err(error)?;
+                OPCODES[line.opcode as usize].length as u32
+            }
+        };
+        if pc > 0x10000 {
+            return Err(error("program runs past the end of memory".to_string()));
+        }
+    }
+
+    // second pass: every label is known, emit the bytes
+    for line in &lines {
+        let error = |message: String| AsmError {
+            line: line.number,
+            message,
+        };
+        let resolve = |expr: &Expr| {
+            expr.evaluate(&labels)
+                .ok_or_else(|| error(format!("undefined label in {}", expr)))
+        };
+
+        let bytes = match line.statement {
+            Statement::Empty => continue,
+            Statement::Org(_) => {
+                segments.push(Segment {
+                    origin: line.address,
+                    bytes: Vec::new(),
+                });
+                continue;
+            }
+            Statement::Bytes(ref values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    bytes.push(to_byte(resolve(value)?).map_err(error)?);
+                }
+                bytes
+            }
+            Statement::Words(ref values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    let word = resolve(value)?;
+                    bytes.push(word as u8);
+                    bytes.push((word >> 8) as u8);
+                }
+                bytes
+            }
+            Statement::Instruction(_, ref operand) => {
+                let opcode = &OPCODES[line.opcode as usize];
+                let mut bytes = vec![line.opcode];
+                let value = match operand.expr() {
+                    Some(expr) => resolve(expr)?,
+                    None => 0,
+                };
+
+                match opcode.mode {
+                    AddressingMode::Implied | AddressingMode::Accumulator => {}
+                    AddressingMode::Relative => {
+                        let offset = value as i32 - (line.address as i32 + 2);
+                        if !(-128..=127).contains(&offset) {
+                            return Err(error(format!("branch target ${:04X} is too far", value)));
+                        }
+                        bytes.push(offset as u8);
+                    }
+                    _ if opcode.length == 2 => bytes.push(to_byte(value).map_err(error)?),
+                    _ => {
+                        bytes.push(value as u8);
+                        bytes.push((value >> 8) as u8);
+                    }
+                }
+                bytes
+            }
+        };
+
+        if segments.is_empty() {
+            segments.push(Segment {
+                origin: 0,
+                bytes: Vec::new(),
+            });
+        }
+        if let Some(segment) = segments.last_mut() {
+            segment.bytes.extend(bytes);
+        }
+    }
+
+    segments.retain(|segment| !segment.bytes.is_empty());
+
+    Ok(Program { segments, labels })
+}
+
+struct Line {
+    number: usize,
+    labels: Vec<String>,
+    statement: Statement,
+    /// address of the first byte, assigned by the first pass
+    address: u16,
+    /// chosen by the first pass for instructions
+    opcode: u8,
+}
+
+enum Statement {
+    Empty,
+    Org(Expr),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+    Instruction(String, Operand),
+}
+
+enum Operand {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    /// zero page, absolute or a branch target
+    Direct(Expr),
+    Indexed(Expr, Index),
+    /// (zp,X)
+    IndexedIndirect(Expr),
+    /// (zp),Y
+    IndirectIndexed(Expr),
+    Indirect(Expr),
+}
+
+impl Operand {
+    fn expr(&self) -> Option<&Expr> {
+        match *self {
+            Operand::None | Operand::Accumulator => None,
+            Operand::Immediate(ref expr) |
+            Operand::Direct(ref expr) |
+            Operand::Indexed(ref expr, _) |
+            Operand::IndexedIndirect(ref expr) |
+            Operand::IndirectIndexed(ref expr) |
+            Operand::Indirect(ref expr) => Some(expr),
+        }
+    }
+}
+
+enum Term {
+    Number(u16),
+    Label(String),
+}
+
+#[derive(PartialEq, Eq)]
+enum Part {
+    Whole,
+    /// <
+    Low,
+    /// >
+    High,
+}
+
+/// A value, `label+offset` or a number, optionally reduced to one of its bytes
+struct Expr {
+    term: Term,
+    offset: i32,
+    part: Part,
+}
+
+impl Expr {
+    fn evaluate(&self, labels: &HashMap<String, u16>) -> Option<u16> {
+        let base = match self.term {
+            Term::Number(value) => value,
+            Term::Label(ref name) => *labels.get(name)?,
+        };
+        let value = (base as i32 + self.offset) as u16;
+
+        Some(match self.part {
+            Part::Whole => value,
+            Part::Low => value & 0xff,
+            Part::High => value >> 8,
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.part {
+            Part::Whole => {}
+            Part::Low => write!(f, "<")?,
+            Part::High => write!(f, ">")?,
+        }
+        match self.term {
+            Term::Number(value) => write!(f, "${:X}", value)?,
+            Term::Label(ref name) => write!(f, "{}", name)?,
+        }
+        if self.offset != 0 {
+            write!(f, "{:+}", self.offset)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(text: &str) -> Result<(Vec<String>, Statement), String> {
+    let mut rest = match text.find(';') {
+        Some(comment) => &text[..comment],
+        None => text,
+    }
+    .trim();
+
+    let mut labels = Vec::new();
+    while let Some(colon) = rest.find(':') {
+        let label = rest[..colon].trim();
+        if !is_identifier(label) {
+            return Err(format!("invalid label {}", label));
+        }
+        labels.push(label.to_string());
+        rest = rest[colon + 1..].trim();
+    }
+
+    if rest.is_empty() {
+        return Ok((labels, Statement::Empty));
+    }
+
+    let (word, operand) = match rest.find(char::is_whitespace) {
+        Some(space) => (&rest[..space], rest[space..].trim()),
+        None => (rest, ""),
+    };
+    let word = word.to_ascii_uppercase();
+
+    let statement = match word.as_str() {
+        ".ORG" => Statement::Org(parse_expr(operand)?),
+        ".BYTE" => Statement::Bytes(parse_list(operand)?),
+        ".WORD" => Statement::Words(parse_list(operand)?),
+        _ if word.starts_with('.') => return Err(format!("unknown directive {}", word)),
+        _ => {
+            // nestest and some assemblers spell ISC as ISB
+            let mnemonic = if word == "ISB" { "ISC".to_string() } else { word };
+            if !OPCODES.iter().any(|opcode| opcode.mnemonic == mnemonic) {
+                return Err(format!("unknown instruction {}", mnemonic));
+            }
+            Statement::Instruction(mnemonic, parse_operand(operand)?)
+        }
+    };
+
+    Ok((labels, statement))
+}
+
+fn parse_list(text: &str) -> Result<Vec<Expr>, String> {
+    text.split(',').map(|value| parse_expr(value.trim())).collect()
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let operand: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let upper = operand.to_ascii_uppercase();
+
+    Ok(if operand.is_empty() {
+        Operand::None
+    } else if upper == "A" {
+        Operand::Accumulator
+    } else if let Some(value) = operand.strip_prefix('#') {
+        Operand::Immediate(parse_expr(value)?)
+    } else if upper.starts_with('(') && upper.ends_with(",X)") {
+        Operand::IndexedIndirect(parse_expr(&operand[1..operand.len() - 3])?)
+    } else if upper.starts_with('(') && upper.ends_with("),Y") {
+        Operand::IndirectIndexed(parse_expr(&operand[1..operand.len() - 3])?)
+    } else if upper.starts_with('(') && upper.ends_with(')') {
+        Operand::Indirect(parse_expr(&operand[1..operand.len() - 1])?)
+    } else if upper.ends_with(",X") {
+        Operand::Indexed(parse_expr(&operand[..operand.len() - 2])?, Index::X)
+    } else if upper.ends_with(",Y") {
+        Operand::Indexed(parse_expr(&operand[..operand.len() - 2])?, Index::Y)
+    } else {
+        Operand::Direct(parse_expr(&operand)?)
+    })
+}
+
+fn parse_expr(text: &str) -> Result<Expr, String> {
+    let (part, text) = match text.chars().next() {
+        Some('<') => (Part::Low, &text[1..]),
+        Some('>') => (Part::High, &text[1..]),
+        _ => (Part::Whole, text),
+    };
+
+    // an operator can't come first, skip the whole first character
+    let skip = text.chars().next().map_or(0, char::len_utf8);
+    let (term, offset) = match text[skip..].find(['+', '-']) {
+        Some(i) => {
+            let i = skip + i;
+            let sign = if text[i..].starts_with('-') { -1 } else { 1 };
+            let offset = parse_number(text[i + 1..].trim())?;
+            (text[..i].trim(), sign * offset as i32)
+        }
+        None => (text, 0),
+    };
+
+    let term = if is_identifier(term) {
+        Term::Label(term.to_string())
+    } else {
+        Term::Number(parse_number(term)?)
+    };
+
+    Ok(Expr { term, offset, part })
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let parsed = if let Some(hex) = text.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(binary) = text.strip_prefix('%') {
+        u16::from_str_radix(binary, 2)
+    } else {
+        text.parse::<u16>()
+    };
+
+    parsed.map_err(|_| format!("invalid value {}", text))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn to_byte(value: u16) -> Result<u8, String> {
+    if value > 0xff {
+        Err(format!("value ${:X} does not fit in a byte", value))
+    } else {
+        Ok(value as u8)
+    }
+}
+
+/// Picks the opcode for an instruction, preferring zero page addressing when the operand is
+/// already known to fit and official opcodes over unofficial ones
+fn select_opcode(
+    mnemonic: &str,
+    operand: &Operand,
+    labels: &HashMap<String, u16>,
+) -> Result<u8, String> {
+    let zero_page = operand
+        .expr()
+        .and_then(|expr| expr.evaluate(labels))
+        .is_some_and(|value| value <= 0xff);
+    let branch = OPCODES
+        .iter()
+        .any(|opcode| opcode.mnemonic == mnemonic && opcode.mode == AddressingMode::Relative);
+
+    let modes = match *operand {
+        Operand::None => vec![AddressingMode::Implied, AddressingMode::Accumulator],
+        Operand::Accumulator => vec![AddressingMode::Accumulator],
+        Operand::Immediate(_) => vec![AddressingMode::Immediate],
+        Operand::Direct(_) if branch => vec![AddressingMode::Relative],
+        Operand::Direct(_) if zero_page => {
+            vec![AddressingMode::ZeroPage, AddressingMode::Absolute]
+        }
+        Operand::Direct(_) => vec![AddressingMode::Absolute],
+        Operand::Indexed(_, index) if zero_page => vec![
+            AddressingMode::ZeroPageIndexed(index),
+            AddressingMode::AbsoluteIndexed(index),
+        ],
+        Operand::Indexed(_, index) => vec![AddressingMode::AbsoluteIndexed(index)],
+        Operand::IndexedIndirect(_) => vec![AddressingMode::IndexedIndirect],
+        Operand::IndirectIndexed(_) => vec![AddressingMode::IndirectIndexed],
+        Operand::Indirect(_) => vec![AddressingMode::Indirect],
+    };
+
+    for mode in modes {
+        let opcode = (0..256)
+            .filter(|&i| OPCODES[i].mnemonic == mnemonic && OPCODES[i].mode == mode)
+            .min_by_key(|&i| !OPCODES[i].official);
+        if let Some(opcode) = opcode {
+            return Ok(opcode as u8);
+        }
+    }
+
+    Err(format!("{} does not support this addressing mode", mnemonic))
+}
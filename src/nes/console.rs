@@ -0,0 +1,163 @@
+use super::cpu::{Cpu, CpuState, Interrupt, IrqSource};
+use super::error::{Error, Result};
+use super::memory::{Memory, NesMemory, RamPattern};
+use super::rom::Rom;
+
+use std::io::{Read, Write};
+
+/// PPU dots per CPU cycle on NTSC
+const DOTS_PER_CYCLE: u8 = 3;
+
+/// The whole console. The CPU owns the bus, which in turn owns the PPU, the APU and the
+/// cartridge, and everything is clocked together one CPU cycle at a time.
+#[derive(Debug)]
+pub struct Nes {
+    pub cpu: Cpu<NesMemory>,
+}
+
+impl Nes {
+    /// Plugs the cartridge in and powers the console on
+    pub fn new(rom: Rom) -> Result<Self> {
+        Nes::with_ram_pattern(rom, RamPattern::default())
+    }
+
+    /// Like new(), filling RAM with the given pattern on every power on
+    pub fn with_ram_pattern(rom: Rom, ram_pattern: RamPattern) -> Result<Self> {
+        let mut memory = NesMemory::new(Box::new(rom))?;
+        memory.ram_pattern = ram_pattern;
+
+        let mut nes = Nes {
+            cpu: Cpu::new(memory),
+        };
+        nes.power_cycle();
+
+        Ok(nes)
+    }
+
+    /// Presses the reset button. RAM and most CPU registers survive it.
+    pub fn reset(&mut self) {
+        self.cpu.memory.reset();
+        self.cpu.reset();
+        self.run_reset_sequence();
+    }
+
+    /// Turns the console off and on again
+    pub fn power_cycle(&mut self) {
+        self.cpu.memory.power_on();
+        self.cpu.power_on();
+        self.run_reset_sequence();
+    }
+
+    /// Runs the CPU's reset sequence with the rest of the console clocked along, so the PPU is
+    /// 21 dots in by the time the first instruction is fetched
+    fn run_reset_sequence(&mut self) {
+        self.tick();
+        while !self.cpu.between_instructions() {
+            self.tick();
+        }
+    }
+
+    /// Runs a single CPU cycle along with the PPU and APU
+    pub fn tick(&mut self) {
+        self.cpu.tick();
+
+        {
+            let bus = &mut self.cpu.memory;
+            for _ in 0..DOTS_PER_CYCLE {
+                bus.ppu.tick();
+            }
+            bus.apu.tick();
+            bus.mapper_mut().tick();
+        }
+
+        let nmi = self.cpu.memory.ppu.nmi();
+        self.cpu.interrupt(Interrupt::Nmi, nmi);
+        let irq = self.cpu.memory.mapper().irq();
+        self.cpu.irq.set(IrqSource::Mapper, irq);
+    }
+
+    /// Executes a single CPU instruction and returns the number of cycles it took
+    pub fn step_instruction(&mut self) -> Result<u8> {
+        let mut cycles = 0u8;
+        loop {
+            self.tick();
+            cycles = cycles.wrapping_add(1);
+            if self.cpu.instruction_complete() {
+                break;
+            }
+        }
+
+        self.check_jam().map(|_| cycles)
+    }
+
+    /// Runs for the given number of CPU cycles. The whole system keeps running even if the CPU
+    /// jams on the way, which is then reported once all cycles ran.
+    pub fn step_cycles(&mut self, cycles: u64) -> Result<()> {
+        for _ in 0..cycles {
+            self.tick();
+        }
+
+        self.check_jam()
+    }
+
+    /// Runs until the next vblank begins
+    pub fn run_frame(&mut self) -> Result<()> {
+        let frame = self.cpu.memory.ppu.frame;
+        while self.cpu.memory.ppu.frame == frame {
+            self.tick();
+            self.check_jam()?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores battery-backed PRG-RAM from a save file. A save shorter than PRG-RAM only fills
+    /// the start of it, and carts without a battery ignore it.
+    pub fn load_save(&mut self, r: &mut dyn Read) -> Result<()> {
+        if let Some(ram) = self.cpu.memory.battery_ram_mut() {
+            let mut save = Vec::with_capacity(ram.len());
+            r.take(ram.len() as u64).read_to_end(&mut save)?;
+            ram[..save.len()].copy_from_slice(&save);
+        }
+
+        Ok(())
+    }
+
+    /// Writes battery-backed PRG-RAM out as a save file, carts without a battery write nothing
+    pub fn write_save(&self, w: &mut dyn Write) -> Result<()> {
+        if let Some(ram) = self.cpu.memory.battery_ram() {
+            w.write_all(ram)?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of sides of a Famicom Disk System disk, 0 for cartridges
+    pub fn disk_sides(&self) -> usize {
+        self.cpu.memory.mapper().disk_sides()
+    }
+
+    /// Side in the disk drive, if any
+    pub fn disk_side(&self) -> Option<usize> {
+        self.cpu.memory.mapper().disk_side()
+    }
+
+    /// Flips or changes the disk, or ejects it with None. As with a real drive, the new side
+    /// takes a moment to show up.
+    pub fn insert_disk(&mut self, side: Option<usize>) -> Result<()> {
+        match side {
+            Some(side) if side >= self.disk_sides() => Err(Error::NoSuchDiskSide(side)),
+            _ => {
+                self.cpu.memory.mapper_mut().insert_disk(side);
+                Ok(())
+            }
+        }
+    }
+
+    fn check_jam(&self) -> Result<()> {
+        match self.cpu.state() {
+            CpuState::Running => Ok(()),
+            CpuState::Jammed { opcode, address } => Err(Error::CpuJam { opcode, address }),
+        }
+    }
+}
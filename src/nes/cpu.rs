@@ -1,6 +1,7 @@
-use super::memory::Memory;
-use super::memory::NesMemory;
-use nes::rom::Rom;
+use super::disasm::{branch_target, AddressingMode, Index, OPCODES};
+use super::error::{Error, Result};
+use super::memory::{is_same_page, Memory};
+use super::trace::Tracer;
 use std::num::Wrapping;
 
 pub static CARRY_FLAG: u8 = 1 << 0;
@@ -11,856 +12,1201 @@ pub static BREAK_FLAG: u8 = 1 << 4;
 pub static OVERFLOW_FLAG: u8 = 1 << 6;
 pub static NEGATIVE_FLAG: u8 = 1 << 7;
 
-pub const INSTRUCTION_NAMES: [&str; 256] = [
-    "BRK", "ORA", "KIL", "SLO", "NOP", "ORA", "ASL", "SLO",
-    "PHP", "ORA", "ASL", "ANC", "NOP", "ORA", "ASL", "SLO",
-    "BPL", "ORA", "KIL", "SLO", "NOP", "ORA", "ASL", "SLO",
-    "CLC", "ORA", "NOP", "SLO", "NOP", "ORA", "ASL", "SLO",
-    "JSR", "AND", "KIL", "RLA", "BIT", "AND", "ROL", "RLA",
-    "PLP", "AND", "ROL", "ANC", "BIT", "AND", "ROL", "RLA",
-    "BMI", "AND", "KIL", "RLA", "NOP", "AND", "ROL", "RLA",
-    "SEC", "AND", "NOP", "RLA", "NOP", "AND", "ROL", "RLA",
-    "RTI", "EOR", "KIL", "SRE", "NOP", "EOR", "LSR", "SRE",
-    "PHA", "EOR", "LSR", "ALR", "JMP", "EOR", "LSR", "SRE",
-    "BVC", "EOR", "KIL", "SRE", "NOP", "EOR", "LSR", "SRE",
-    "CLI", "EOR", "NOP", "SRE", "NOP", "EOR", "LSR", "SRE",
-    "RTS", "ADC", "KIL", "RRA", "NOP", "ADC", "ROR", "RRA",
-    "PLA", "ADC", "ROR", "ARR", "JMP", "ADC", "ROR", "RRA",
-    "BVS", "ADC", "KIL", "RRA", "NOP", "ADC", "ROR", "RRA",
-    "SEI", "ADC", "NOP", "RRA", "NOP", "ADC", "ROR", "RRA",
-    "NOP", "STA", "NOP", "SAX", "STY", "STA", "STX", "SAX",
-    "DEY", "NOP", "TXA", "XAA", "STY", "STA", "STX", "SAX",
-    "BCC", "STA", "KIL", "AHX", "STY", "STA", "STX", "SAX",
-    "TYA", "STA", "TXS", "TAS", "SHY", "STA", "SHX", "AHX",
-    "LDY", "LDA", "LDX", "LAX", "LDY", "LDA", "LDX", "LAX",
-    "TAY", "LDA", "TAX", "LAX", "LDY", "LDA", "LDX", "LAX",
-    "BCS", "LDA", "KIL", "LAX", "LDY", "LDA", "LDX", "LAX",
-    "CLV", "LDA", "TSX", "LAS", "LDY", "LDA", "LDX", "LAX",
-    "CPY", "CMP", "NOP", "DCP", "CPY", "CMP", "DEC", "DCP",
-    "INY", "CMP", "DEX", "AXS", "CPY", "CMP", "DEC", "DCP",
-    "BNE", "CMP", "KIL", "DCP", "NOP", "CMP", "DEC", "DCP",
-    "CLD", "CMP", "NOP", "DCP", "NOP", "CMP", "DEC", "DCP",
-    "CPX", "SBC", "NOP", "ISC", "CPX", "SBC", "INC", "ISC",
-    "INX", "SBC", "NOP", "SBC", "CPX", "SBC", "INC", "ISC",
-    "BEQ", "SBC", "KIL", "ISC", "NOP", "SBC", "INC", "ISC",
-    "SED", "SBC", "NOP", "ISC", "NOP", "SBC", "INC", "ISC",
-];
+/// Constant ORed into the accumulator by the unstable XAA and immediate LAX opcodes. The real
+/// value depends on the chip and temperature; $EE matches what most 2A03s produce.
+const UNSTABLE_MAGIC: u8 = 0xee;
 
 const NMI_ADDR: u16 = 0xfffa;
 const RESET_ADDR: u16 = 0xfffc;
 const BRK_ADDR: u16 = 0xfffe;
 
+/// 6502 core, generic over the bus it is attached to
 #[derive(Debug)]
-pub struct Cpu {
+pub struct Cpu<M: Memory> {
     pub registers: Registers,
-    pub memory: NesMemory,
-    pub nmi: bool,
-    pub irq: bool,
-    pub rst: bool,
+    pub memory: M,
+    /// level of the NMI line, which is edge triggered
+    nmi: bool,
+    /// set when the NMI line is asserted, until the NMI is serviced
+    nmi_pending: bool,
+    /// shared IRQ line, serviced for as long as any source holds it and I is clear
+    pub irq: IrqLine,
+    /// reset request, cleared once the reset sequence starts
+    rst: bool,
+    /// value of I seen by the next interrupt poll when the instruction that just finished
+    /// changed it on its last cycle (CLI, SEI, PLP), as the poll happens before that cycle
+    delayed_interrupt_flag: Option<bool>,
     pub cycle: u64,
+    state: CpuState,
+    /// instruction (or interrupt sequence) currently being executed
+    instruction: Instruction<M>,
+    /// cycle within the current instruction, 0 when the next opcode is due to be fetched
+    step: u8,
+    /// effective address being built up by the addressing mode
+    address: u16,
+    /// internal data latch (operands, pointers and values being modified)
+    data: u8,
+    /// whether indexing carried into the high byte of the effective address
+    page_crossed: bool,
+    /// when set, a nestest.log style line is written for every instruction executed
+    pub tracer: Option<Tracer>,
 }
 
-impl Cpu {
-    pub fn new(mut rom: Box<Rom>) -> Self {
-        println!("Initializing cpu with rom {:?}", rom);
-
-        let mut cpu = Cpu {
+impl<M: Memory> Cpu<M> {
+    pub fn new(memory: M) -> Self {
+        Cpu {
             registers: Registers::default(),
-            memory: NesMemory::new(rom),
+            memory,
             nmi: false,
-            irq: false,
+            nmi_pending: false,
+            irq: IrqLine::default(),
             rst: false,
+            delayed_interrupt_flag: None,
             cycle: 0,
+            state: CpuState::Running,
+            instruction: Self::decode(0xea),
+            step: 0,
+            address: 0,
+            data: 0,
+            page_crossed: false,
+            tracer: None,
+        }
+    }
+
+    /// Executes a single instruction (servicing a pending interrupt first) and returns the number
+    /// of CPU cycles it took. If called part way through an instruction started with tick(), only
+    /// the remainder of that instruction is executed. A jammed CPU only lets a single cycle pass.
+    pub fn execute_instruction(&mut self) -> u8 {
+        let start = self.cycle;
+        loop {
+            self.tick();
+            if self.instruction_complete() {
+                break;
+            }
+        }
+
+        (self.cycle - start) as u8
+    }
+
+    /// Like execute_instruction(), but fails once the CPU has jammed
+    pub fn step(&mut self) -> Result<u8> {
+        let cycles = self.execute_instruction();
+        match self.state {
+            CpuState::Running => Ok(cycles),
+            CpuState::Jammed { opcode, address } => Err(Error::CpuJam { opcode, address }),
+        }
+    }
+
+    /// Whether the last tick() finished an instruction (an interrupt sequence only counts
+    /// together with the first instruction of its handler), or the CPU has jammed
+    pub fn instruction_complete(&self) -> bool {
+        self.is_jammed() || (self.step == 0 && !self.instruction.is_interrupt())
+    }
+
+    /// Whether the last tick() finished an instruction or an interrupt sequence
+    pub fn between_instructions(&self) -> bool {
+        self.step == 0
+    }
+
+    pub fn state(&self) -> CpuState {
+        self.state
+    }
+
+    pub fn is_jammed(&self) -> bool {
+        self.state != CpuState::Running
+    }
+
+    /// Runs the CPU for exactly one cycle, performing the single bus access (read or write) the
+    /// 6502 makes on that cycle, including the dummy reads and writes of the real chip.
+    pub fn tick(&mut self) {
+        if self.is_jammed() {
+            // the clock keeps running, but only a reset gets the cpu going again
+            if !self.rst {
+                self.cycle += 1;
+                return;
+            }
+            self.state = CpuState::Running;
+            self.step = 0;
+        }
+
+        if self.step == 0 {
+            self.begin_instruction();
+            self.step = 1;
+        } else {
+            let step = self.step;
+            let interrupt_flag = self.registers.get_flag(INTERRUPT_FLAG);
+            let done = match self.instruction.operation {
+                Operation::Push(_) | Operation::Pull(_) | Operation::Jsr | Operation::Rts |
+                Operation::Rti | Operation::Brk | Operation::Interrupt(_) => self.stack_step(step),
+                Operation::Kil => {
+                    self.state = CpuState::Jammed {
+                        opcode: self.instruction.opcode,
+                        address: self.registers.program_counter.wrapping_sub(1),
+                    };
+                    true
+                }
+                _ => self.addressing_step(step),
+            };
+
+            self.step = if done { 0 } else { step + 1 };
+            if done && interrupt_flag != self.registers.get_flag(INTERRUPT_FLAG) {
+                self.delayed_interrupt_flag = Some(interrupt_flag);
+            }
+        }
+
+        self.cycle += 1;
+    }
+
+    /// Fetches the next opcode, or starts the interrupt sequence if an interrupt is pending
+    fn begin_instruction(&mut self) {
+        let pc = self.registers.program_counter;
+        let interrupt = self.pending_interrupt();
+        self.delayed_interrupt_flag = None;
+
+        if let Some(interrupt) = interrupt {
+            // the opcode fetch still happens, but its result is discarded
+            self.read(pc);
+            self.instruction = Instruction {
+                opcode: 0x00,
+                mode: AddressingMode::Implied,
+                operation: Operation::Interrupt(interrupt),
+            };
+            return;
+        }
+
+        if self.tracer.is_some() {
+            let line = self.trace_line();
+            if let Some(ref mut tracer) = self.tracer {
+                tracer.write_line(&line);
+            }
+        }
+
+        let opcode = self.read(pc);
+        self.registers.program_counter = pc.wrapping_add(1);
+        self.instruction = Self::decode(opcode);
+    }
+
+    /// Formats the instruction at the program counter and the current registers as a line of a
+    /// nestest.log trace. Operands are peeked, so building the line has no effect on the bus.
+    /// The PPU position comes from the bus, or is derived from the cycle count on buses without
+    /// a PPU, as if it had been running since cycle 0.
+    pub fn trace_line(&self) -> String {
+        let pc = self.registers.program_counter;
+        let opcode = self.memory.peek(pc);
+        let instruction = Self::decode(opcode);
+        let length = OPCODES[opcode as usize].length;
+
+        let bytes = (0..length)
+            .map(|i| format!("{:02X}", self.memory.peek(pc.wrapping_add(i as u16))))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // nestest marks unofficial opcodes with a '*' and spells ISC as ISB
+        let mnemonic = match OPCODES[opcode as usize].mnemonic {
+            "ISC" => "ISB",
+            name => name,
         };
+        let unofficial = if OPCODES[opcode as usize].official { " " } else { "*" };
+        let disassembly = format!("{} {}", mnemonic, self.trace_operand(&instruction));
+
+        let (scanline, dot) = self.memory.ppu_position().unwrap_or_else(|| {
+            let dot = self.cycle * 3;
+            (((dot / 341) % 262) as u16, (dot % 341) as u16)
+        });
+        let r = &self.registers;
+        format!(
+            "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            pc,
+            bytes,
+            unofficial,
+            disassembly.trim_end(),
+            r.accumulator,
+            r.x,
+            r.y,
+            r.processor_status,
+            r.stack_pointer,
+            scanline,
+            dot,
+            self.cycle
+        )
+    }
+
+    /// Formats the operand of the instruction at the program counter the way nestest does,
+    /// including the effective address and the value currently stored there
+    fn trace_operand(&self, instruction: &Instruction<M>) -> String {
+        let pc = self.registers.program_counter;
+        let byte = self.memory.peek(pc.wrapping_add(1));
+        let word = (self.memory.peek(pc.wrapping_add(2)) as u16) << 8 | byte as u16;
+        let peek_word_zp = |pointer: u8| {
+            let low = self.memory.peek(pointer as u16) as u16;
+            let high = self.memory.peek(pointer.wrapping_add(1) as u16) as u16;
+            high << 8 | low
+        };
+        let r = &self.registers;
+
+        match instruction.mode {
+            AddressingMode::Implied => String::new(),
+            AddressingMode::Accumulator => "A".to_string(),
+            AddressingMode::Immediate => format!("#${:02X}", byte),
+            AddressingMode::ZeroPage => {
+                format!("${:02X} = {:02X}", byte, self.memory.peek(byte as u16))
+            }
+            AddressingMode::ZeroPageIndexed(index) => {
+                let address = byte.wrapping_add(r.register_from_index(index));
+                format!(
+                    "${:02X},{:?} @ {:02X} = {:02X}",
+                    byte,
+                    index,
+                    address,
+                    self.memory.peek(address as u16)
+                )
+            }
+            AddressingMode::Absolute => match instruction.operation {
+                Operation::Jmp | Operation::Jsr => format!("${:04X}", word),
+                _ => format!("${:04X} = {:02X}", word, self.memory.peek(word)),
+            },
+            AddressingMode::AbsoluteIndexed(index) => {
+                let address = word.wrapping_add(r.register_from_index(index) as u16);
+                format!(
+                    "${:04X},{:?} @ {:04X} = {:02X}",
+                    word,
+                    index,
+                    address,
+                    self.memory.peek(address)
+                )
+            }
+            AddressingMode::IndexedIndirect => {
+                let pointer = byte.wrapping_add(r.x);
+                let address = peek_word_zp(pointer);
+                format!(
+                    "(${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                    byte,
+                    pointer,
+                    address,
+                    self.memory.peek(address)
+                )
+            }
+            AddressingMode::IndirectIndexed => {
+                let base = peek_word_zp(byte);
+                let address = base.wrapping_add(r.y as u16);
+                format!(
+                    "(${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                    byte,
+                    base,
+                    address,
+                    self.memory.peek(address)
+                )
+            }
+            AddressingMode::Relative => format!("${:04X}", branch_target(pc, byte)),
+            AddressingMode::Indirect => {
+                let low = self.memory.peek(word) as u16;
+                let high = self.memory.peek((word & 0xff00) | (word.wrapping_add(1) & 0x00ff));
+                format!("(${:04X}) = {:04X}", word, (high as u16) << 8 | low)
+            }
+        }
+    }
+
+    // Bus access
+
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory.load(address)
+    }
 
-        cpu
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory.store(address, value);
     }
 
-    pub fn execute_instruction(&mut self) {
-        // interrupts
-        self.do_interrupts();
+    /// reads the byte at the program counter and increments it
+    fn fetch(&mut self) -> u8 {
+        let pc = self.registers.program_counter;
+        self.registers.program_counter = pc.wrapping_add(1);
+        self.read(pc)
+    }
 
-        let instruction = self.memory.load(self.registers.program_counter);
-        self.registers.program_counter += 1;
+    fn stack_address(&self) -> u16 {
+        0x0100 | (self.registers.stack_pointer as u16)
+    }
 
-        let opcode = INSTRUCTION_NAMES[instruction as usize];
-        println!(
-            "({}) {} from pc: {:x}",
-            self.cycle, opcode, self.registers.program_counter
-        );
-        let mut cycles = 0;
+    /// writes a byte to the stack as one bus cycle
+    fn push_cycle(&mut self, value: u8) {
+        let address = self.stack_address();
+        self.write(address, value);
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
+    }
+
+    /// pulls a byte from the stack as one bus cycle
+    fn pull_cycle(&mut self) -> u8 {
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
+        let address = self.stack_address();
+        self.read(address)
+    }
+
+    pub fn push(&mut self, value: u8) {
+        let sp = self.registers.stack_pointer;
+        self.memory.store(0x0100 | (sp as u16), value);
+        self.registers.stack_pointer = sp.wrapping_sub(1);
+    }
+
+    pub fn push_word(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
+    }
 
-        match instruction {
+    pub fn pop(&mut self) -> u8 {
+        self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
+        self.memory
+            .load(0x0100 | (self.registers.stack_pointer as u16))
+    }
+
+    pub fn pop_word(&mut self) -> u16 {
+        let low = self.pop() as u16;
+        let high = self.pop() as u16;
+
+        (high << 8) | low
+    }
+
+    /// Puts the registers in their power-on state and starts the reset sequence
+    pub fn power_on(&mut self) {
+        self.registers = Registers {
+            processor_status: 0x34,
+            ..Registers::default()
+        };
+        self.cycle = 0;
+        self.reset();
+    }
+
+    /// Starts the reset sequence, as when the reset button is pressed. The instruction being
+    /// executed is abandoned and pending interrupts are dropped; the next 7 ticks run the
+    /// sequence (do_interrupts() runs it right away). The registers keep their values apart from
+    /// I, which is set, and the stack pointer, which drops by 3 since reset is an interrupt
+    /// sequence with its pushes turned into reads.
+    pub fn reset(&mut self) {
+        self.rst = true;
+        self.step = 0;
+        self.nmi_pending = false;
+        self.irq = IrqLine::default();
+        self.delayed_interrupt_flag = None;
+    }
+
+    fn shift_left(&mut self, value: u8, lsb: bool) -> u8 {
+        let mut result = value << 1;
+        if lsb {
+            result |= 1;
+        }
+        self.registers.set_flag(CARRY_FLAG, (value & 0x80) != 0);
+        self.registers.set_zn(result);
+        result
+    }
+
+    fn shift_right(&mut self, value: u8, msb: bool) -> u8 {
+        let mut result = value >> 1;
+        if msb {
+            result |= 0x80;
+        }
+
+        self.registers.set_flag(CARRY_FLAG, (value & 0x1) != 0);
+        self.registers.set_zn(result);
+        result
+    }
+
+    fn transfer(from: u8, to: &mut u8) {
+        *to = from;
+    }
+
+    fn compare(&mut self, x: u8, y: u8) {
+        let result = (Wrapping(x as u32) - Wrapping(y as u32)).0;
+        self.registers.set_flag(CARRY_FLAG, (result & 0x100) == 0);
+        self.registers.set_zn(result as u8);
+    }
+
+    // Instruction decoding
+
+    fn decode(opcode: u8) -> Instruction<M> {
+        use self::Operation::*;
+
+        let operation = match opcode {
             // Storage
 
             // LDA
-            0xa1 | 0xa5 | 0xa9 | 0xad | 0xb1 | 0xb5 | 0xb9 | 0xbd => {
-                let result = self.alu_address(instruction);
-                self.lda(result.0);
-                cycles = result.1;
-            }
+            0xa1 | 0xa5 | 0xa9 | 0xad | 0xb1 | 0xb5 | 0xb9 | 0xbd => Read(Self::lda),
             // LDX
-            0xa2 | 0xa6 | 0xae | 0xb6 | 0xbe => {
-                let result = self.rmw_address(instruction);
-                self.ldx(result.0);
-                cycles = result.1;
-            }
+            0xa2 | 0xa6 | 0xae | 0xb6 | 0xbe => Read(Self::ldx),
             // LDY
-            0xa0 | 0xa4 | 0xac | 0xb4 | 0xbc => {
-                let result = self.control_address(instruction);
-                self.ldy(result.0);
-                cycles = result.1;
-            }
+            0xa0 | 0xa4 | 0xac | 0xb4 | 0xbc => Read(Self::ldy),
             // STA
-            0x81 | 0x85 | 0x8d | 0x91 | 0x95 | 0x99 | 0x9d => {
-                let result = self.alu_address(instruction);
-                self.sta(result.0);
-                cycles = result.1;
-            }
+            0x81 | 0x85 | 0x8d | 0x91 | 0x95 | 0x99 | 0x9d => Write(Self::sta),
             // STX
-            0x86 | 0x8e | 0x96 => {
-                let result = self.rmw_address(instruction);
-                self.stx(result.0);
-                cycles = result.1;
-            }
+            0x86 | 0x8e | 0x96 => Write(Self::stx),
             // STY
-            0x84 | 0x8c | 0x94 => {
-                let result = self.control_address(instruction);
-                self.sty(result.0);
-                cycles = result.1;
-            }
+            0x84 | 0x8c | 0x94 => Write(Self::sty),
             // TAX
-            0xaa => {
-                cycles = 2;
-                self.tax();
-            }
+            0xaa => Implied(Self::tax),
             // TAY
-            0xa8 => {
-                cycles = 2;
-                self.tay();
-            }
+            0xa8 => Implied(Self::tay),
             // TXA
-            0x8a => {
-                cycles = 2;
-                self.txa();
-            }
+            0x8a => Implied(Self::txa),
             // TYA
-            0x98 => {
-                cycles = 2;
-                self.tya();
-            }
+            0x98 => Implied(Self::tya),
             // TSX
-            0xba => {
-                cycles = 2;
-                self.tsx();
-            }
+            0xba => Implied(Self::tsx),
             // TXS
-            0x9a => {
-                cycles = 2;
-                self.txs();
-            }
+            0x9a => Implied(Self::txs),
 
             // Stack
 
             // PHA
-            0x48 => {
-                cycles = 3;
-                self.pha();
-            }
+            0x48 => Push(Self::pha),
             // PHP
-            0x08 => {
-                cycles = 3;
-                self.php();
-            }
+            0x08 => Push(Self::php),
             // PLA
-            0x68 => {
-                cycles = 4;
-                self.pla();
-            }
+            0x68 => Pull(Self::pla),
             // PLP
-            0x28 => {
-                cycles = 4;
-                self.plp();
-            }
+            0x28 => Pull(Self::plp),
 
             // Bitwise
 
             // AND
-            0x21 | 0x25 | 0x29 | 0x2d | 0x31 | 0x35 | 0x39 | 0x3d => {
-                let result = self.alu_address(instruction);
-                self.and(result.0);
-                cycles = result.1;
-            }
+            0x21 | 0x25 | 0x29 | 0x2d | 0x31 | 0x35 | 0x39 | 0x3d => Read(Self::and),
             // EOR
-            0x41 | 0x45 | 0x49 | 0x4d | 0x51 | 0x55 | 0x59 | 0x5d => {
-                let result = self.alu_address(instruction);
-                self.eor(result.0);
-                cycles = result.1;
-            }
+            0x41 | 0x45 | 0x49 | 0x4d | 0x51 | 0x55 | 0x59 | 0x5d => Read(Self::eor),
             // ORA
-            0x01 | 0x05 | 0x09 | 0x0d | 0x11 | 0x15 | 0x19 | 0x1d => {
-                let result = self.alu_address(instruction);
-                self.ora(result.0);
-                cycles = result.1;
-            }
+            0x01 | 0x05 | 0x09 | 0x0d | 0x11 | 0x15 | 0x19 | 0x1d => Read(Self::ora),
             // BIT
-            0x24 | 0x2c => {
-                let result = self.control_address(instruction);
-                self.bit(result.0);
-                cycles = result.1;
-            }
+            0x24 | 0x2c => Read(Self::bit),
             // ASL
-            0x0a | 0x06 | 0x16 | 0x0e | 0x1e => {
-                if instruction == 0x0a {
-                    self.asl_accumulator();
-                    cycles = 2;
-                } else {
-                    let address;
-                    match instruction {
-                        0x06 => {
-                            address = self.zero_page_address();
-                            cycles = 5;
-                        }
-                        0x16 => {
-                            address = self.zero_page_indexed_address(Index::X);
-                            cycles = 6;
-                        }
-                        0x1e => {
-                            let result = self.absolute_indexed_address(Index::X);
-                            address = result.0;
-                            cycles = 7 + result.1;
-                        }
-                        0x0e => {
-                            address = self.absolute_address();
-                            cycles = 6;
-                        }
-                        _ => panic!("Unreachable"),
-                    }
-
-                    self.asl(address);
-                }
-            }
+            0x0a | 0x06 | 0x16 | 0x0e | 0x1e => Modify(Self::asl),
             // LSR
-            0x4a | 0x46 | 0x56 | 0x4e | 0x5e => {
-                if instruction == 0x4a {
-                    self.lsr_accumulator();
-                    cycles = 2;
-                } else {
-                    let address;
-                    match instruction {
-                        0x46 => {
-                            address = self.zero_page_address();
-                            cycles = 5;
-                        }
-                        0x56 => {
-                            address = self.zero_page_indexed_address(Index::X);
-                            cycles = 6;
-                        }
-                        0x4e => {
-                            address = self.absolute_address();
-                            cycles = 6;
-                        }
-                        0x5e => {
-                            let result = self.absolute_indexed_address(Index::X);
-                            address = result.0;
-                            cycles = 7 + result.1;
-                        }
-                        _ => panic!("Unreachable"),
-                    }
-
-                    self.lsr(address);
-                }
-            }
+            0x4a | 0x46 | 0x56 | 0x4e | 0x5e => Modify(Self::lsr),
             // ROL
-            0x2a | 0x26 | 0x36 | 0x2e | 0x3e => {
-                if instruction == 0x2a {
-                    self.rol_accumulator();
-                    cycles = 2;
-                } else {
-                    let address;
-                    match instruction {
-                        0x26 => {
-                            address = self.zero_page_address();
-                            cycles = 5;
-                        }
-                        0x36 => {
-                            address = self.zero_page_indexed_address(Index::X);
-                            cycles = 6;
-                        }
-                        0x2e => {
-                            address = self.absolute_address();
-                            cycles = 6;
-                        }
-                        0x3e => {
-                            let result = self.absolute_indexed_address(Index::X);
-                            address = result.0;
-                            cycles = 7 + result.1;
-                        }
-                        _ => panic!("Unreachable"),
-                    }
-
-                    self.rol(address);
-                }
-            }
+            0x2a | 0x26 | 0x36 | 0x2e | 0x3e => Modify(Self::rol),
             // ROR
-            0x6a | 0x66 | 0x76 | 0x6e | 0x7e => {
-                if instruction == 0x6a {
-                    self.ror_accumulator();
-                    cycles = 2;
-                } else {
-                    let address;
-                    match instruction {
-                        0x66 => {
-                            address = self.zero_page_address();
-                            cycles = 5;
-                        }
-                        0x76 => {
-                            address = self.zero_page_indexed_address(Index::X);
-                            cycles = 6;
-                        }
-                        0x6e => {
-                            address = self.absolute_address();
-                            cycles = 6;
-                        }
-                        0x7e => {
-                            let result = self.absolute_indexed_address(Index::X);
-                            address = result.0;
-                            cycles = 7 + result.1;
-                        }
-                        _ => panic!("Unreachable"),
-                    }
-                    self.ror(address);
-                }
-            }
+            0x6a | 0x66 | 0x76 | 0x6e | 0x7e => Modify(Self::ror),
 
             // Math
 
             // ADC
-            0x61 | 0x65 | 0x69 | 0x6d | 0x71 | 0x75 | 0x79 | 0x7d => {
-                let result = self.alu_address(instruction);
-                self.adc(result.0);
-                cycles = result.1;
-            }
-            //SBC
-            0xe1 | 0xe5 | 0xeb | 0xe9 | 0xed | 0xf1 | 0xf5 | 0xf9 | 0xfd => {
-                let result = self.alu_address(instruction);
-                self.sbc(result.0);
-                cycles = result.1;
-            }
+            0x61 | 0x65 | 0x69 | 0x6d | 0x71 | 0x75 | 0x79 | 0x7d => Read(Self::adc),
+            // SBC
+            0xe1 | 0xe5 | 0xeb | 0xe9 | 0xed | 0xf1 | 0xf5 | 0xf9 | 0xfd => Read(Self::sbc),
             // DEC
-            0xc6 | 0xd6 | 0xce | 0xde => {
-                let address;
-                match instruction {
-                    0xc6 => {
-                        address = self.zero_page_address();
-                        cycles = 5;
-                    }
-                    0xd6 => {
-                        address = self.zero_page_indexed_address(Index::X);
-                        cycles = 6;
-                    }
-                    0xce => {
-                        address = self.absolute_address();
-                        cycles = 6;
-                    }
-                    0xde => {
-                        let result = self.absolute_indexed_address(Index::X);
-                        address = result.0;
-                        cycles = 7 + result.1;
-                    }
-                    _ => panic!("Unreachable"),
-                }
-
-                self.dec(address);
-            }
+            0xc6 | 0xd6 | 0xce | 0xde => Modify(Self::dec),
             // DEX
-            0xca => {
-                self.dex();
-                cycles = 2;
-            }
+            0xca => Implied(Self::dex),
             // DEY
-            0x88 => {
-                self.dey();
-                cycles = 2;
-            }
+            0x88 => Implied(Self::dey),
             // INC
-            0xe6 | 0xf6 | 0xfe | 0xee => {
-                let address;
-                match instruction {
-                    0xe6 => {
-                        address = self.zero_page_address();
-                        cycles = 6;
-                    }
-                    0xf6 => {
-                        address = self.zero_page_indexed_address(Index::X);
-                        cycles = 6;
-                    }
-                    0xfe => {
-                        let result = self.absolute_indexed_address(Index::X);
-                        address = result.0;
-                        cycles = 7 + result.1;
-                    }
-                    0xee => {
-                        address = self.absolute_address();
-                        cycles = 6;
-                    }
-                    _ => panic!("Unreachable"),
-                }
-
-                self.inc(address);
-            }
+            0xe6 | 0xf6 | 0xfe | 0xee => Modify(Self::inc),
             // INX
-            0xe8 => {
-                self.inx();
-                cycles = 2;
-            }
+            0xe8 => Implied(Self::inx),
             // INY
-            0xc8 => {
-                self.iny();
-                cycles = 2;
-            }
+            0xc8 => Implied(Self::iny),
 
             // Registers
 
             // CLC
-            0x18 => {
-                self.clc();
-                cycles = 2;
-            }
+            0x18 => Implied(Self::clc),
             // CLI
-            0x58 => {
-                self.cli();
-                cycles = 2;
-            }
+            0x58 => Implied(Self::cli),
             // CLV
-            0xb8 => {
-                self.clv();
-                cycles = 2;
-            }
+            0xb8 => Implied(Self::clv),
             // CLD
-            0xd8 => {
-                self.cld();
-                cycles = 2;
-            }
+            0xd8 => Implied(Self::cld),
             // SEC
-            0x38 => {
-                self.sec();
-                cycles = 2;
-            }
+            0x38 => Implied(Self::sec),
             // SEI
-            0x78 => {
-                self.sei();
-                cycles = 2;
-            }
+            0x78 => Implied(Self::sei),
+            // SED
+            0xf8 => Implied(Self::sed),
             // CMP
-            0xc1 | 0xc5 | 0xc9 | 0xcd | 0xd1 | 0xd5 | 0xd9 | 0xdd => {
-                let result = self.alu_address(instruction);
-                self.cmp(result.0);
-                cycles = result.1;
-            }
+            0xc1 | 0xc5 | 0xc9 | 0xcd | 0xd1 | 0xd5 | 0xd9 | 0xdd => Read(Self::cmp),
             // CPX
-            0xe0 | 0xe4 | 0xec => {
-                let result = self.control_address(instruction);
-                self.cpx(result.0);
-                cycles = result.1;
-            }
+            0xe0 | 0xe4 | 0xec => Read(Self::cpx),
             // CPY
-            0xc0 | 0xc4 | 0xcc => {
-                let result = self.control_address(instruction);
-                self.cpy(result.0);
-                cycles = result.1;
-            }
+            0xc0 | 0xc4 | 0xcc => Read(Self::cpy),
 
             // Branch
 
             // BCC
-            0x90 => {
-                let result = self.control_address(instruction);
-                cycles = self.bcc(result.0);
-                cycles += result.1;
-            }
+            0x90 => Branch(Self::bcc),
             // BCS
-            0xb0 => {
-                let result = self.control_address(instruction);
-                cycles = self.bcs(result.0);
-                cycles += result.1;
-            }
+            0xb0 => Branch(Self::bcs),
             // BEQ
-            0xf0 => {
-                let result = self.control_address(instruction);
-                cycles = self.beq(result.0);
-                cycles += result.1;
-            }
+            0xf0 => Branch(Self::beq),
             // BMI
-            0x30 => {
-                let result = self.control_address(instruction);
-                cycles = self.bmi(result.0);
-                cycles += result.1;
-            }
+            0x30 => Branch(Self::bmi),
             // BNE
-            0xd0 => {
-                let result = self.control_address(instruction);
-                cycles = self.bne(result.0);
-                cycles += result.1;
-            }
+            0xd0 => Branch(Self::bne),
             // BPL
-            0x10 => {
-                let result = self.control_address(instruction);
-                cycles = self.bpl(result.0);
-                cycles += result.1;
-            }
+            0x10 => Branch(Self::bpl),
             // BVC
-            0x50 => {
-                let result = self.control_address(instruction);
-                cycles = self.bvc(result.0);
-                cycles += result.1;
-            }
+            0x50 => Branch(Self::bvc),
             // BVS
-            0x70 => {
-                let result = self.control_address(instruction);
-                cycles = self.bvs(result.0);
-                cycles += result.1;
-            }
+            0x70 => Branch(Self::bvs),
 
             // Jump
 
             // JMP
-            0x4c | 0x6c => {
-                let address;
-                if instruction == 0x4c {
-                    address = self.absolute_address();
-                    cycles = 3;
-                } else {
-                    address = self.indirect_address();
-                    cycles = 5;
-                }
-
-                self.jmp(address);
-            }
+            0x4c => Jmp,
+            0x6c => Jmp,
             // JSR
-            0x20 => {
-                let address = self.absolute_address();
-                self.jsr(address);
-                cycles = 6;
-            }
+            0x20 => Jsr,
             // RTS
-            0x60 => {
-                self.rts();
-                cycles = 6;
-            }
+            0x60 => Rts,
             // RTI
-            0x40 => {
-                self.rti();
-                cycles = 6;
-            }
+            0x40 => Rti,
 
             // System
 
             // BRK
-            0x00 => {
-                self.brk();
-                cycles = 7;
-            }
+            0x00 => Brk,
             // NOP
-            0xea => {
-                // No op
-                cycles = 2;
+            0xea | 0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => Implied(Self::nop),
+            0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => Read(Self::nop_read),
+            0x04 | 0x44 | 0x64 | 0x0c | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x1c | 0x3c |
+            0x5c | 0x7c | 0xdc | 0xfc => Read(Self::nop_read),
+            // KIL
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2 => {
+                Kil
             }
 
-            _ => panic!("Unrecognized opcode {:x}", instruction),
-        }
+            // Unofficial
+
+            // SLO
+            0x03 | 0x07 | 0x0f | 0x13 | 0x17 | 0x1b | 0x1f => Modify(Self::slo),
+            // RLA
+            0x23 | 0x27 | 0x2f | 0x33 | 0x37 | 0x3b | 0x3f => Modify(Self::rla),
+            // SRE
+            0x43 | 0x47 | 0x4f | 0x53 | 0x57 | 0x5b | 0x5f => Modify(Self::sre),
+            // RRA
+            0x63 | 0x67 | 0x6f | 0x73 | 0x77 | 0x7b | 0x7f => Modify(Self::rra),
+            // DCP
+            0xc3 | 0xc7 | 0xcf | 0xd3 | 0xd7 | 0xdb | 0xdf => Modify(Self::dcp),
+            // ISC
+            0xe3 | 0xe7 | 0xef | 0xf3 | 0xf7 | 0xfb | 0xff => Modify(Self::isc),
+            // SAX
+            0x83 | 0x87 | 0x8f | 0x97 => Write(Self::sax),
+            // LAX
+            0xa3 | 0xa7 | 0xaf | 0xb3 | 0xb7 | 0xbf => Read(Self::lax),
+            0xab => Read(Self::lxa),
+            // ANC
+            0x0b | 0x2b => Read(Self::anc),
+            // ALR
+            0x4b => Read(Self::alr),
+            // ARR
+            0x6b => Read(Self::arr),
+            // AXS
+            0xcb => Read(Self::axs),
+            // XAA
+            0x8b => Read(Self::xaa),
+            // LAS
+            0xbb => Read(Self::las),
+            // AHX
+            0x93 | 0x9f => UnstableStore(Self::ahx),
+            // TAS
+            0x9b => UnstableStore(Self::tas),
+            // SHY
+            0x9c => UnstableStore(Self::shy),
+            // SHX
+            0x9e => UnstableStore(Self::shx),
+        };
 
-        //        self.cycle += cycles as u64;
-        self.cycle += 1 as u64;
+        Instruction {
+            opcode,
+            mode: OPCODES[opcode as usize].mode,
+            operation,
+        }
     }
 
-    /// load byte from memory at given address, setting zero and negative flags as appropriate
-    fn load(&mut self, address: u16) -> u8 {
-        let result = self.memory.load(address);
-        self.registers.set_zn(result);
-
-        result
-    }
+    // Addressing modes
 
-    fn load_word(&mut self, address: u16) -> u16 {
-        let low = self.memory.load(address) as u16;
-        let high = self.memory.load(address + 1) as u16;
-        self.registers.program_counter += 2;
-        low | high << 8
+    /// Performs one cycle of the current instruction's addressing mode, handing over to
+    /// operand_step() once the effective address is known. Returns true when the instruction is
+    /// complete.
+    fn addressing_step(&mut self, step: u8) -> bool {
+        match self.instruction.mode {
+            AddressingMode::Implied => {
+                let pc = self.registers.program_counter;
+                self.read(pc);
+                if let Operation::Implied(operation) = self.instruction.operation {
+                    operation(self);
+                }
+                true
+            }
+            AddressingMode::Accumulator => {
+                let pc = self.registers.program_counter;
+                self.read(pc);
+                if let Operation::Modify(operation) = self.instruction.operation {
+                    let a = self.registers.accumulator;
+                    self.registers.accumulator = operation(self, a);
+                }
+                true
+            }
+            AddressingMode::Immediate => {
+                self.address = self.registers.program_counter;
+                self.registers.program_counter = self.address.wrapping_add(1);
+                self.operand_step(0)
+            }
+            AddressingMode::ZeroPage => match step {
+                1 => {
+                    self.address = self.fetch() as u16;
+                    false
+                }
+                _ => self.operand_step(step - 2),
+            },
+            AddressingMode::ZeroPageIndexed(index) => match step {
+                1 => {
+                    self.address = self.fetch() as u16;
+                    false
+                }
+                2 => {
+                    // the unindexed address is read while the index is added
+                    let address = self.address;
+                    self.read(address);
+                    let offset = self.registers.register_from_index(index);
+                    self.address = (address as u8).wrapping_add(offset) as u16;
+                    false
+                }
+                _ => self.operand_step(step - 3),
+            },
+            AddressingMode::Absolute => match step {
+                1 => {
+                    self.address = self.fetch() as u16;
+                    false
+                }
+                2 => {
+                    let high = self.fetch() as u16;
+                    self.address |= high << 8;
+                    self.jump_step()
+                }
+                _ => self.operand_step(step - 3),
+            },
+            AddressingMode::AbsoluteIndexed(index) => match step {
+                1 => {
+                    self.address = self.fetch() as u16;
+                    false
+                }
+                2 => {
+                    let high = self.fetch() as u16;
+                    let offset = self.registers.register_from_index(index);
+                    self.index_address(high << 8 | self.address, offset);
+                    false
+                }
+                3 => self.fix_address_step(),
+                _ => self.operand_step(step - 4),
+            },
+            AddressingMode::IndexedIndirect => match step {
+                1 => {
+                    self.data = self.fetch();
+                    false
+                }
+                2 => {
+                    let pointer = self.data;
+                    self.read(pointer as u16);
+                    self.data = pointer.wrapping_add(self.registers.x);
+                    false
+                }
+                3 => {
+                    let pointer = self.data;
+                    self.address = self.read(pointer as u16) as u16;
+                    false
+                }
+                4 => {
+                    // the pointer wraps around within the zero page
+                    let pointer = self.data.wrapping_add(1);
+                    let high = self.read(pointer as u16) as u16;
+                    self.address |= high << 8;
+                    false
+                }
+                _ => self.operand_step(step - 5),
+            },
+            AddressingMode::IndirectIndexed => match step {
+                1 => {
+                    self.data = self.fetch();
+                    false
+                }
+                2 => {
+                    let pointer = self.data;
+                    self.address = self.read(pointer as u16) as u16;
+                    false
+                }
+                3 => {
+                    let pointer = self.data.wrapping_add(1);
+                    let high = self.read(pointer as u16) as u16;
+                    let y = self.registers.y;
+                    self.index_address(high << 8 | self.address, y);
+                    false
+                }
+                4 => self.fix_address_step(),
+                _ => self.operand_step(step - 5),
+            },
+            AddressingMode::Relative => self.branch_step(step),
+            AddressingMode::Indirect => match step {
+                1 => {
+                    self.address = self.fetch() as u16;
+                    false
+                }
+                2 => {
+                    let high = self.fetch() as u16;
+                    self.address |= high << 8;
+                    false
+                }
+                3 => {
+                    let address = self.address;
+                    self.data = self.read(address);
+                    false
+                }
+                _ => {
+                    // 6502 has a bug where it only increments the low byte instead
+                    // of the entire 16-bit address.
+                    let address = self.address;
+                    let high = self.read((address & 0xff00) | (address.wrapping_add(1) & 0x00ff));
+                    self.registers.program_counter = (high as u16) << 8 | self.data as u16;
+                    true
+                }
+            },
+        }
     }
 
-    fn store(&mut self, address: u16, value: u8) {
-        self.memory.store(address, value);
+    /// Adds an index to the low byte of a base address, leaving the high byte to be fixed up a
+    /// cycle later if the addition carried.
+    fn index_address(&mut self, base: u16, offset: u8) {
+        let low = (base & 0x00ff) + offset as u16;
+        self.page_crossed = low > 0xff;
+        self.address = (base & 0xff00) | (low & 0x00ff);
+        self.data = (base >> 8) as u8;
     }
 
-    pub fn push(&mut self, value: u8) {
-        let sp = self.registers.stack_pointer;
-        self.memory.store(0x0100 | (sp as u16), value);
-        self.registers.stack_pointer -= 1;
-    }
+    /// Reads from the partially indexed address. Reads that didn't cross a page finish here,
+    /// otherwise the read is a dummy and the high byte is fixed up. Returns true when the
+    /// instruction is complete.
+    fn fix_address_step(&mut self) -> bool {
+        if !self.page_crossed {
+            if let Operation::Read(_) = self.instruction.operation {
+                return self.operand_step(0);
+            }
+        }
 
-    pub fn push_word(&mut self, value: u16) {
-        self.push((value >> 8) as u8);
-        self.push(value as u8);
+        let address = self.address;
+        self.read(address);
+        if self.page_crossed {
+            self.address = address.wrapping_add(0x0100);
+        }
+        false
     }
 
-    pub fn pop(&mut self) -> u8 {
-        self.registers.stack_pointer += 1;
-        self.memory
-            .load(0x0100 | (self.registers.stack_pointer as u16))
+    /// Completes JMP absolute, which only needs the address itself
+    fn jump_step(&mut self) -> bool {
+        match self.instruction.operation {
+            Operation::Jmp => {
+                self.registers.program_counter = self.address;
+                true
+            }
+            _ => false,
+        }
     }
 
-    pub fn pop_word(&mut self) -> u16 {
-        let low = self.pop() as u16;
-        let high = self.pop() as u16;
-
-        (high << 8) | low
+    /// Performs cycle `phase` of the access to the effective address. Returns true when the
+    /// instruction is complete.
+    fn operand_step(&mut self, phase: u8) -> bool {
+        let address = self.address;
+        match (self.instruction.operation, phase) {
+            (Operation::Read(operation), _) => {
+                let value = self.read(address);
+                operation(self, value);
+                true
+            }
+            (Operation::Write(operation), _) => {
+                let value = operation(self);
+                self.write(address, value);
+                true
+            }
+            (Operation::UnstableStore(operation), _) => {
+                let value = operation(self);
+                self.unstable_store(value);
+                true
+            }
+            (Operation::Modify(_), 0) => {
+                self.data = self.read(address);
+                false
+            }
+            (Operation::Modify(operation), 1) => {
+                // the unmodified value is written back while the operation is performed
+                let value = self.data;
+                self.write(address, value);
+                self.data = operation(self, value);
+                false
+            }
+            (Operation::Modify(_), _) => {
+                let value = self.data;
+                self.write(address, value);
+                true
+            }
+            _ => true,
+        }
     }
 
-    /// resets all registers
-    pub fn reset(&mut self) {
-        self.registers.accumulator = 0;
-        self.registers.x = 0;
-        self.registers.y = 0;
-        self.registers.set_flag(INTERRUPT_FLAG, true);
-        self.registers.stack_pointer = 0xfd;
-        self.registers.program_counter = self.load_word(RESET_ADDR);
-        println!("pc set to {:x}", self.registers.program_counter)
+    /// Performs one cycle of a relative branch. Returns true when the instruction is complete.
+    fn branch_step(&mut self, step: u8) -> bool {
+        match step {
+            1 => {
+                self.data = self.fetch();
+                match self.instruction.operation {
+                    Operation::Branch(condition) => !condition(self),
+                    _ => true,
+                }
+            }
+            2 => {
+                let pc = self.registers.program_counter;
+                self.read(pc);
+                let target = (pc as i32 + (self.data as i8) as i32) as u16;
+                self.address = target;
+                self.registers.program_counter = (pc & 0xff00) | (target & 0x00ff);
+                is_same_page(pc, target)
+            }
+            _ => {
+                let pc = self.registers.program_counter;
+                self.read(pc);
+                self.registers.program_counter = self.address;
+                true
+            }
+        }
     }
 
-    fn shift_left(&mut self, value: u8, lsb: bool) -> u8 {
-        let mut result = ((value as u16) << 1) as u8;
-        if lsb {
-            result |= 1;
+    /// Performs one cycle of the instructions that use the stack: pushes, pulls, subroutine
+    /// calls and returns, BRK and the interrupt sequences. Returns true when the instruction is
+    /// complete.
+    fn stack_step(&mut self, step: u8) -> bool {
+        let pc = self.registers.program_counter;
+        match self.instruction.operation {
+            Operation::Push(operation) => match step {
+                1 => {
+                    self.read(pc);
+                    false
+                }
+                _ => {
+                    let value = operation(self);
+                    self.push_cycle(value);
+                    true
+                }
+            },
+            Operation::Pull(operation) => match step {
+                1 => {
+                    self.read(pc);
+                    false
+                }
+                2 => {
+                    let address = self.stack_address();
+                    self.read(address);
+                    false
+                }
+                _ => {
+                    let value = self.pull_cycle();
+                    operation(self, value);
+                    true
+                }
+            },
+            Operation::Jsr => match step {
+                1 => {
+                    self.data = self.fetch();
+                    false
+                }
+                2 => {
+                    let address = self.stack_address();
+                    self.read(address);
+                    false
+                }
+                3 => {
+                    self.push_cycle((pc >> 8) as u8);
+                    false
+                }
+                4 => {
+                    self.push_cycle(pc as u8);
+                    false
+                }
+                _ => {
+                    let high = self.read(pc) as u16;
+                    self.registers.program_counter = high << 8 | self.data as u16;
+                    true
+                }
+            },
+            Operation::Rts => match step {
+                1 => {
+                    self.read(pc);
+                    false
+                }
+                2 => {
+                    let address = self.stack_address();
+                    self.read(address);
+                    false
+                }
+                3 => {
+                    self.address = self.pull_cycle() as u16;
+                    false
+                }
+                4 => {
+                    let high = self.pull_cycle() as u16;
+                    self.registers.program_counter = high << 8 | self.address;
+                    false
+                }
+                _ => {
+                    self.fetch();
+                    true
+                }
+            },
+            Operation::Rti => match step {
+                1 => {
+                    self.read(pc);
+                    false
+                }
+                2 => {
+                    let address = self.stack_address();
+                    self.read(address);
+                    false
+                }
+                3 => {
+                    let flags = self.pull_cycle();
+                    self.registers.set_flags(flags);
+                    false
+                }
+                4 => {
+                    self.address = self.pull_cycle() as u16;
+                    false
+                }
+                _ => {
+                    let high = self.pull_cycle() as u16;
+                    self.registers.program_counter = high << 8 | self.address;
+                    true
+                }
+            },
+            _ => self.interrupt_step(step),
         }
-        self.registers.set_flag(CARRY_FLAG, (value & 0x80) != 0);
-        let val = result as u8;
-        self.registers.set_zn(val);
-        val
     }
 
-    fn shift_right(&mut self, value: u8, msb: bool) -> u8 {
-        let mut result = value >> 1;
-        if msb {
-            result |= 0x80;
-        }
+    /// Performs one cycle of BRK or of an interrupt sequence. Returns true when the sequence is
+    /// complete.
+    ///
+    /// The vector is picked when the status is pushed, so an NMI that arrives before then hijacks
+    /// a BRK or IRQ: the handler at the NMI vector runs, with the pushed B flag telling a BRK
+    /// apart.
+    fn interrupt_step(&mut self, step: u8) -> bool {
+        let pc = self.registers.program_counter;
+        let reset = matches!(self.instruction.operation, Operation::Interrupt(Interrupt::Rst));
 
-        self.registers.set_flag(CARRY_FLAG, (value & 0x1) != 0);
-        let val = result as u8;
-        self.registers.set_zn(val);
-        val
-    }
+        match step {
+            1 => {
+                // BRK skips its padding byte, interrupts leave the program counter alone
+                if let Operation::Brk = self.instruction.operation {
+                    self.fetch();
+                } else {
+                    self.read(pc);
+                }
+                false
+            }
+            2..=4 => {
+                let value = match step {
+                    2 => (pc >> 8) as u8,
+                    3 => pc as u8,
+                    _ => self.pushed_status(),
+                };
+
+                if step == 4 {
+                    self.address = match self.instruction.operation {
+                        Operation::Interrupt(Interrupt::Rst) => RESET_ADDR,
+                        Operation::Interrupt(Interrupt::Nmi) => NMI_ADDR,
+                        _ if self.nmi_pending => {
+                            self.nmi_pending = false;
+                            NMI_ADDR
+                        }
+                        _ => BRK_ADDR,
+                    };
+                }
 
-    fn transfer(from: u8, to: &mut u8) {
-        *to = from;
+                if reset {
+                    // writes are suppressed during reset, so the pushes turn into reads
+                    let address = self.stack_address();
+                    self.read(address);
+                    self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
+                } else {
+                    self.push_cycle(value);
+                }
+                false
+            }
+            5 => {
+                let vector = self.address;
+                self.data = self.read(vector);
+                self.registers.set_flag(INTERRUPT_FLAG, true);
+                false
+            }
+            _ => {
+                let vector = self.address;
+                let high = self.read(vector.wrapping_add(1)) as u16;
+                self.registers.program_counter = high << 8 | self.data as u16;
+                true
+            }
+        }
     }
 
-    fn compare(&mut self, x: u8, y: u8) {
-        let result = (Wrapping(x as u32) - Wrapping(y as u32)).0;
-        self.registers.set_flag(CARRY_FLAG, (result & 0x100) == 0);
-        self.registers.set_zn(result as u8);
+    /// Status pushed by BRK or an interrupt sequence. Bit 5 is always set, B only for BRK.
+    fn pushed_status(&self) -> u8 {
+        let status = self.registers.processor_status | 0x20;
+        match self.instruction.operation {
+            Operation::Brk => status | BREAK_FLAG,
+            _ => status & !BREAK_FLAG,
+        }
     }
 
-    fn branch(&mut self, address: u16) -> u8 {
-        let mut cycles = 1;
-        let pc = self.registers.program_counter;
-        if !NesMemory::is_same_page(pc, address) {
-            cycles += 1;
+    /// Shared store behaviour of AHX, TAS, SHY and SHX. The value written is ANDed with the high
+    /// byte of the base address plus one, and if indexing crossed a page the written value also
+    /// replaces the high byte of the effective address, as the 2A03 does.
+    fn unstable_store(&mut self, value: u8) {
+        let high = self.data.wrapping_add(1);
+        let value = value & high;
+        let mut address = self.address;
+        if self.page_crossed {
+            address = ((value as u16) << 8) | (address & 0x00ff);
         }
-        self.registers.program_counter = address;
-        cycles
+
+        self.write(address, value);
     }
 
     // Instructions
 
     /// Loads a byte into the accumulator register from memory
-    fn lda(&mut self, address: u16) {
-        let result = self.load(address);
-        self.registers.accumulator = result;
+    fn lda(&mut self, value: u8) {
+        self.registers.set_zn(value);
+        self.registers.accumulator = value;
     }
     /// Load byte into index x register from memory
-    fn ldx(&mut self, address: u16) {
-        let result = self.load(address);
-        self.registers.x = result;
+    fn ldx(&mut self, value: u8) {
+        self.registers.set_zn(value);
+        self.registers.x = value;
     }
     /// Loads a byte into the index y register from memory
-    fn ldy(&mut self, address: u16) {
-        let result = self.load(address);
-        self.registers.y = result;
+    fn ldy(&mut self, value: u8) {
+        self.registers.set_zn(value);
+        self.registers.y = value;
     }
     /// Stores a byte in memory from the accumulator registry
-    fn sta(&mut self, address: u16) {
-        let a = self.registers.accumulator;
-        self.store(address, a);
+    fn sta(&mut self) -> u8 {
+        self.registers.accumulator
     }
     /// Stores a byte in memory from the index x register
-    fn stx(&mut self, address: u16) {
-        let x = self.registers.x;
-        self.store(address, x);
+    fn stx(&mut self) -> u8 {
+        self.registers.x
     }
     /// Stores a byte in memory from the index y register
-    fn sty(&mut self, address: u16) {
-        let y = self.registers.y;
-        self.store(address, y);
+    fn sty(&mut self) -> u8 {
+        self.registers.y
     }
     /// Transfers the value of the accumulator registry into the index x registry
     fn tax(&mut self) {
         let value = self.registers.accumulator;
         self.registers.set_zn(value);
 
-        Cpu::transfer(value, &mut self.registers.x);
+        Self::transfer(value, &mut self.registers.x);
     }
     /// Transfers the value in the accumulator registry into the index y registry
     fn tay(&mut self) {
         let value = self.registers.accumulator;
         self.registers.set_zn(value);
 
-        Cpu::transfer(value, &mut self.registers.y);
+        Self::transfer(value, &mut self.registers.y);
     }
     /// Transfers the value in the index x registry into the accumulator registry
     fn txa(&mut self) {
         let value = self.registers.x;
         self.registers.set_zn(value);
 
-        Cpu::transfer(value, &mut self.registers.accumulator);
+        Self::transfer(value, &mut self.registers.accumulator);
     }
     /// Transfers the value in the index y registry into the accumulator registry
     fn tya(&mut self) {
         let value = self.registers.y;
         self.registers.set_zn(value);
 
-        Cpu::transfer(value, &mut self.registers.accumulator);
+        Self::transfer(value, &mut self.registers.accumulator);
     }
     /// Transfers the value in the stack pointer registry into the index x registry
     fn tsx(&mut self) {
         let value = self.registers.stack_pointer;
         self.registers.set_zn(value);
 
-        Cpu::transfer(value, &mut self.registers.x);
+        Self::transfer(value, &mut self.registers.x);
     }
     /// Transfers the value in the index x registry into the stack pointer registry
     fn txs(&mut self) {
         let value = self.registers.x;
-        self.registers.set_zn(value);
-
-        Cpu::transfer(value, &mut self.registers.stack_pointer);
+        Self::transfer(value, &mut self.registers.stack_pointer);
     }
     /// Pushes the value of the accumulator registry onto the stack
-    fn pha(&mut self) {
-        let a = self.registers.accumulator;
-
-        self.push(a);
+    fn pha(&mut self) -> u8 {
+        self.registers.accumulator
     }
     /// Pushes status flags onto the stack
-    fn php(&mut self) {
-        let mut flags = self.registers.processor_status;
-        self.push(flags | BREAK_FLAG);
+    fn php(&mut self) -> u8 {
+        self.registers.processor_status | BREAK_FLAG
     }
     /// Loads a byte from the stack into the accumulator registry
-    fn pla(&mut self) {
-        let value = self.pop();
+    fn pla(&mut self, value: u8) {
         self.registers.set_zn(value);
         self.registers.accumulator = value;
     }
     /// Pulls a byte from the stack and into the processor status registry
-    fn plp(&mut self) {
-        let value = self.pop();
+    fn plp(&mut self, value: u8) {
         self.registers.set_flags(value);
     }
     /// Performs a logical AND on a byte from memory and the accumulator's value, and stores the
     /// result in the accumulator
-    fn and(&mut self, address: u16) {
-        let value = self.memory.load(address);
-
-        self.registers.set_zn(value);
-        self.registers.accumulator &= value;
+    fn and(&mut self, value: u8) {
+        let result = self.registers.accumulator & value;
+        self.registers.set_zn(result);
+        self.registers.accumulator = result;
     }
     /// Performs an exclusive OR on a byte of memory and the accumulator's value, and stores the
     /// result in the accumulator
-    fn eor(&mut self, address: u16) {
-        let value = self.memory.load(address);
-        self.registers.set_zn(value);
-        self.registers.accumulator ^= value;
+    fn eor(&mut self, value: u8) {
+        let result = self.registers.accumulator ^ value;
+        self.registers.set_zn(result);
+        self.registers.accumulator = result;
     }
     /// Performs an inclusive OR on a byte of memory and the accumulator's value, and stores the
     /// result in the accumulator
-    fn ora(&mut self, address: u16) {
-        let value = self.memory.load(address);
-        self.registers.set_zn(value);
-        self.registers.accumulator |= value;
+    fn ora(&mut self, value: u8) {
+        let result = self.registers.accumulator | value;
+        self.registers.set_zn(result);
+        self.registers.accumulator = result;
     }
     /// Tests if one or more bits is set in the supplied memory location. The accumulator's value
     /// is ANDed with the value in memory to set the zero flag, and the value in memory's 6th
     /// and 7th bits are used to set the negative and overflow flag respectively.
-    fn bit(&mut self, address: u16) {
-        let value = self.memory.load(address);
+    fn bit(&mut self, value: u8) {
         let a = self.registers.accumulator;
         self.registers.set_flag(ZERO_FLAG, (value & a) == 0);
         self.registers.set_flag(NEGATIVE_FLAG, (value & 0x80) != 0);
         self.registers.set_flag(OVERFLOW_FLAG, (value & 0x40) != 0);
     }
-    /// Shifts the contents of memory at the given address left. Effectively multiplies memory
-    /// contents by two (ignoring two's complement), and sets the carry bit if the result will
-    /// not fit in 8 bits.
-    fn asl(&mut self, address: u16) {
-        let value = self.memory.load(address);
-        let result = self.shift_left(value, false);
-        self.memory.store(address, result);
-    }
-    /// Shifts the contents of the accumulator registry to the left (see fn asl())
-    fn asl_accumulator(&mut self) {
-        let a = self.registers.accumulator;
-        let result = self.shift_left(a, false);
-        Cpu::transfer(result, &mut self.registers.accumulator);
-    }
-    /// Shifts the contents of memory at the given address right.
-    fn lsr(&mut self, address: u16) {
-        let value = self.memory.load(address);
-        let result = self.shift_right(value, false);
-        self.memory.store(address, result);
-    }
-    /// Shifts the contents of the accumulator registry to the right (see fn lsr())
-    fn lsr_accumulator(&mut self) {
-        let value = self.registers.accumulator;
-        let result = self.shift_right(value, false);
-        Cpu::transfer(result, &mut self.registers.accumulator);
-    }
-    /// Shifts the contents of memory at the supplied address to the left, setting bit 0 with the
-    /// current carry flag.
-    fn rol(&mut self, address: u16) {
-        let value = self.memory.load(address);
-        let carry = self.registers.get_flag(CARRY_FLAG);
-        let result = self.shift_left(value, carry);
-        self.memory.store(address, result);
+    /// Shifts a value left. Effectively multiplies it by two (ignoring two's complement), and
+    /// sets the carry bit if the result will not fit in 8 bits.
+    fn asl(&mut self, value: u8) -> u8 {
+        self.shift_left(value, false)
     }
-    /// Shift the contents of the accumulator registry to the left, setting bit 0 with the current
-    /// carry flag.
-    fn rol_accumulator(&mut self) {
-        let a = self.registers.accumulator;
-        let carry = self.registers.get_flag(CARRY_FLAG);
-        let result = self.shift_left(a, carry);
-        Cpu::transfer(result, &mut self.registers.accumulator);
+    /// Shifts a value right.
+    fn lsr(&mut self, value: u8) -> u8 {
+        self.shift_right(value, false)
     }
-    /// Shifts the contents of memory at the supplied address to the right, setting bit 7 with the
-    /// current carry flag.
-    fn ror(&mut self, address: u16) {
-        let value = self.memory.load(address);
+    /// Shifts a value to the left, setting bit 0 with the current carry flag.
+    fn rol(&mut self, value: u8) -> u8 {
         let carry = self.registers.get_flag(CARRY_FLAG);
-        let result = self.shift_right(value, carry);
-        self.memory.store(address, result);
+        self.shift_left(value, carry)
     }
-    /// Shifts the contents of the accumulator registry to the right, setting bit 7 with the current
-    /// carry flag.
-    fn ror_accumulator(&mut self) {
-        let a = self.registers.accumulator;
+    /// Shifts a value to the right, setting bit 7 with the current carry flag.
+    fn ror(&mut self, value: u8) -> u8 {
         let carry = self.registers.get_flag(CARRY_FLAG);
-        let result = self.shift_right(a, carry);
-        Cpu::transfer(result, &mut self.registers.accumulator);
+        self.shift_right(value, carry)
     }
-    /// Adds the contents of memory at the given address to the value of the accumulator, setting
-    /// the carry bit if an overflow occurs.
-    fn adc(&mut self, address: u16) {
-        let value = self.memory.load(address);
+    /// Adds a value and the carry flag to the accumulator, setting carry, overflow, zero and
+    /// negative flags as appropriate.
+    fn adc(&mut self, value: u8) {
         let mut result = self.registers.accumulator as u32 + value as u32;
         if self.registers.get_flag(CARRY_FLAG) {
             result += 1;
@@ -877,14 +1223,13 @@ impl Cpu {
         self.registers.set_zn(result);
         self.registers.accumulator = result;
     }
-    /// Subtracts the contents of memory at the given address from the value of the accumulator,
-    /// clearing the carry if overflow occurs.
-    fn sbc(&mut self, address: u16) {
-        let value = self.memory.load(address);
+    /// Subtracts a value and the inverted carry flag from the accumulator, clearing the carry if
+    /// a borrow occurs.
+    fn sbc(&mut self, value: u8) {
         let a = self.registers.accumulator;
-        let mut result = a as u32 - value as u32;
+        let mut result = (a as u32).wrapping_sub(value as u32);
         if !self.registers.get_flag(CARRY_FLAG) {
-            result -= 1;
+            result = result.wrapping_sub(1);
         }
 
         self.registers.set_flag(CARRY_FLAG, (result & 0x100) == 0);
@@ -897,41 +1242,41 @@ impl Cpu {
         self.registers.set_zn(result);
         self.registers.accumulator = result;
     }
-    /// Decreases the value of the given address in memory by one
-    fn dec(&mut self, address: u16) {
-        let value = self.memory.load(address) - 1;
+    /// Decreases a value in memory by one
+    fn dec(&mut self, value: u8) -> u8 {
+        let value = value.wrapping_sub(1);
         self.registers.set_zn(value);
-        self.memory.store(address, value);
+        value
     }
     /// Decreases the value of the index x registry by one
     fn dex(&mut self) {
         let value = (Wrapping(self.registers.x) - Wrapping(1)).0;
         self.registers.set_zn(value);
-        Cpu::transfer(value, &mut self.registers.x);
+        Self::transfer(value, &mut self.registers.x);
     }
     /// Decreases the value of the index y registry by one
     fn dey(&mut self) {
         let value = (Wrapping(self.registers.y) - Wrapping(1)).0;
         self.registers.set_zn(value);
-        Cpu::transfer(value, &mut self.registers.y);
+        Self::transfer(value, &mut self.registers.y);
     }
-    /// Adds one to the value of memory at the given address, and stores at the address.
-    fn inc(&mut self, address: u16) {
-        let value = self.memory.load(address) + 1;
+    /// Adds one to a value in memory
+    fn inc(&mut self, value: u8) -> u8 {
+        let value = value.wrapping_add(1);
         self.registers.set_zn(value);
-        self.memory.store(address, value);
+        value
     }
     /// Adds one to the value of the index x registry
     fn inx(&mut self) {
         let value = (Wrapping(self.registers.x) + Wrapping(1)).0;
         self.registers.set_zn(value);
-        Cpu::transfer(value, &mut self.registers.x);
+        Self::transfer(value, &mut self.registers.x);
     }
     /// Adds one to the value of the index y registry
     fn iny(&mut self) {
         let value = (Wrapping(self.registers.y) + Wrapping(1)).0;
         self.registers.set_zn(value);
-        Cpu::transfer(value, &mut self.registers.y);
+        Self::transfer(value, &mut self.registers.y);
     }
     /// Sets the carry flag to zero
     fn clc(&mut self) {
@@ -957,465 +1302,324 @@ impl Cpu {
     fn sei(&mut self) {
         self.registers.set_flag(INTERRUPT_FLAG, true);
     }
+    /// Sets the decimal flag to one. The 2A03 has no decimal mode, so this has no other effect.
+    fn sed(&mut self) {
+        self.registers.set_flag(DECIMAL_FLAG, true);
+    }
     /// Compares the contents of the accumulator with a value in memory, setting zero and negative
     /// flags as appropriate
-    fn cmp(&mut self, address: u16) {
+    fn cmp(&mut self, value: u8) {
         let a = self.registers.accumulator;
-        let value = self.memory.load(address);
         self.compare(a, value);
     }
     /// Compares the contents of the index x registry with a value in memory, setting zero and
     /// negative flags as appropriate
-    fn cpx(&mut self, address: u16) {
+    fn cpx(&mut self, value: u8) {
         let x = self.registers.x;
-        let value = self.memory.load(address);
         self.compare(x, value);
     }
-    /// Compares the contents of the index x registry with a value in memory, setting zero and
+    /// Compares the contents of the index y registry with a value in memory, setting zero and
     /// negative flags as appropriate
-    fn cpy(&mut self, address: u16) {
+    fn cpy(&mut self, value: u8) {
         let y = self.registers.y;
-        let value = self.memory.load(address);
         self.compare(y, value);
     }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// carry flag is not set
-    fn bcc(&mut self, address: u16) -> u8 {
-        if !self.registers.get_flag(CARRY_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
-    }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// carry flag is set
-    fn bcs(&mut self, address: u16) -> u8 {
-        if self.registers.get_flag(CARRY_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
-    }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// zero flag is set
-    fn beq(&mut self, address: u16) -> u8 {
-        if self.registers.get_flag(ZERO_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
+    /// Does nothing
+    fn nop(&mut self) {}
+    /// Reads its operand and does nothing with it
+    fn nop_read(&mut self, _value: u8) {}
+    /// Branches if the carry flag is not set
+    fn bcc(&self) -> bool {
+        !self.registers.get_flag(CARRY_FLAG)
     }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// negative flag is set
-    fn bmi(&mut self, address: u16) -> u8 {
-        if self.registers.get_flag(NEGATIVE_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
+    /// Branches if the carry flag is set
+    fn bcs(&self) -> bool {
+        self.registers.get_flag(CARRY_FLAG)
     }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// zero flag is not set
-    fn bne(&mut self, address: u16) -> u8 {
-        if !self.registers.get_flag(ZERO_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
+    /// Branches if the zero flag is set
+    fn beq(&self) -> bool {
+        self.registers.get_flag(ZERO_FLAG)
     }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// negative flag is not set
-    fn bpl(&mut self, address: u16) -> u8 {
-        if !self.registers.get_flag(NEGATIVE_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
+    /// Branches if the negative flag is set
+    fn bmi(&self) -> bool {
+        self.registers.get_flag(NEGATIVE_FLAG)
     }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// overflow flag is not set
-    fn bvc(&mut self, address: u16) -> u8 {
-        if !self.registers.get_flag(OVERFLOW_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
+    /// Branches if the zero flag is not set
+    fn bne(&self) -> bool {
+        !self.registers.get_flag(ZERO_FLAG)
     }
-    /// Adds the supplied address to the program counter (causing a branch to a new location) if the
-    /// overflow flag is set
-    fn bvs(&mut self, address: u16) -> u8 {
-        if self.registers.get_flag(OVERFLOW_FLAG) {
-            self.branch(address)
-        } else {
-            0
-        }
+    /// Branches if the negative flag is not set
+    fn bpl(&self) -> bool {
+        !self.registers.get_flag(NEGATIVE_FLAG)
     }
-    /// Sets the program counter to the supplied address
-    fn jmp(&mut self, address: u16) {
-        self.registers.program_counter = address;
+    /// Branches if the overflow flag is not set
+    fn bvc(&self) -> bool {
+        !self.registers.get_flag(OVERFLOW_FLAG)
     }
-    ///
-    fn jsr(&mut self, address: u16) {
-        let value = self.registers.program_counter - 1;
-        self.push_word(value);
-        self.registers.program_counter = address;
+    /// Branches if the overflow flag is set
+    fn bvs(&self) -> bool {
+        self.registers.get_flag(OVERFLOW_FLAG)
     }
-    ///
-    fn rts(&mut self) {
-        let value = self.pop_word() + 1;
-        self.registers.program_counter = value;
+
+    // Unofficial instructions
+
+    /// Shifts a value in memory left, then ORs the result into the accumulator (ASL + ORA)
+    fn slo(&mut self, value: u8) -> u8 {
+        let result = self.shift_left(value, false);
+        self.ora(result);
+        result
     }
-    ///
-    fn rti(&mut self) {
-        let flags = self.pop();
-        self.registers.set_flags(flags);
-        self.registers.program_counter = self.pop_word();
+    /// Rotates a value in memory left, then ANDs the result into the accumulator (ROL + AND)
+    fn rla(&mut self, value: u8) -> u8 {
+        let result = self.rol(value);
+        self.and(result);
+        result
     }
-    ///
-    fn brk(&mut self) {
-        let pc = self.registers.program_counter + 1;
-        self.push_word(pc);
-        let flags = self.registers.processor_status;
-        self.push(flags | BREAK_FLAG);
-        self.registers.set_flag(INTERRUPT_FLAG, true);
-        let pc = self.load_word(BRK_ADDR);
-        self.registers.program_counter = pc;
-        println!("BRK -- setting pc to: {:x}", pc);
+    /// Shifts a value in memory right, then exclusive ORs the result into the accumulator
+    /// (LSR + EOR)
+    fn sre(&mut self, value: u8) -> u8 {
+        let result = self.shift_right(value, false);
+        self.eor(result);
+        result
     }
-
-    // Addressing modes
-
-    fn alu_address(&mut self, opcode: u8) -> (u16, u8) {
-        let mut cycles: u8;
-        let address: u16;
-        if opcode & 0x10 == 0 {
-            match (opcode >> 2) & 0x03 {
-                0x00 => {
-                    cycles = 6;
-                    address = self.indexed_indirect_address();
-                }
-                0x01 => {
-                    cycles = 5;
-                    address = self.zero_page_address();
-                }
-                0x02 => {
-                    cycles = 2;
-                    address = self.immediate_address();
-                }
-                0x03 => {
-                    cycles = 4;
-                    address = self.absolute_address();
-                    println!("got addr {:x}", address);
-                }
-                _ => panic!("unknown alu operation {:#x}", (opcode >> 2) & 0x03),
-            }
-        } else {
-            match (opcode >> 2) & 0x03 {
-                0x00 => {
-                    cycles = 5;
-                    let result = self.indirect_indexed_address();
-                    address = result.0;
-                    cycles += result.1;
-                }
-                0x01 => {
-                    cycles = 4;
-                    address = self.zero_page_indexed_address(Index::X);
-                }
-                0x02 => {
-                    cycles = 4;
-                    let result = self.absolute_indexed_address(Index::Y);
-                    address = result.0;
-                    cycles += result.1;
-                }
-                0x03 => {
-                    cycles = 4;
-                    let result = self.absolute_indexed_address(Index::X);
-                    address = result.0;
-                    cycles += result.1;
-                }
-                _ => panic!("unknown operation {:#x}", (opcode >> 2) & 0x03),
-            }
-        }
-
-        (address, cycles)
+    /// Rotates a value in memory right, then adds the result to the accumulator using the carry
+    /// shifted out by the rotate (ROR + ADC)
+    fn rra(&mut self, value: u8) -> u8 {
+        let result = self.ror(value);
+        self.adc(result);
+        result
     }
-
-    fn rmw_address(&mut self, opcode: u8) -> (u16, u8) {
-        let mut cycles = 0;
-        let address;
-
-        if opcode & 0x10 == 0 {
-            match (opcode >> 2) & 0x03 {
-                0x00 => {
-                    cycles = 2;
-                    address = self.immediate_address();
-                }
-                0x01 => {
-                    cycles = 3;
-                    address = self.zero_page_address();
-                }
-                0x02 => {
-                    cycles = 2;
-                    address = 0; // not used
-                }
-                0x03 => {
-                    cycles = 4;
-                    address = self.absolute_address();
-                }
-                _ => panic!("unknown rmw operation {:#x}", (opcode >> 2) & 0x03),
-            };
-        } else {
-            let index: Index;
-            match (opcode >> 2) & 0x03 {
-                0x00 => {
-                    cycles = 2;
-                    address = 0; // not used
-                }
-                0x01 => {
-                    cycles = 4;
-
-                    match opcode & 0xf0 {
-                        0x90 => index = Index::Y,
-                        0xb0 => index = Index::Y,
-                        _ => index = Index::X,
-                    }
-
-                    address = self.zero_page_indexed_address(index);
-                }
-                0x02 => {
-                    cycles = 2;
-                    address = 0; // not used
-                }
-                0x03 => {
-                    cycles = 4;
-
-                    match opcode & 0xf0 {
-                        0x90 => index = Index::Y,
-                        0xb0 => index = Index::Y,
-                        _ => index = Index::X,
-                    }
-
-                    let result = self.absolute_indexed_address(index);
-                    address = result.0;
-                    cycles += result.1;
-                }
-                _ => panic!("unknown operation {:#x}", (opcode >> 2) & 0x03),
-            }
-        }
-
-        (address, cycles)
+    /// Decreases a value in memory by one, then compares it with the accumulator (DEC + CMP)
+    fn dcp(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.cmp(result);
+        result
     }
-
-    fn control_address(&mut self, opcode: u8) -> (u16, u8) {
-        let mut cycles = 0;
-        let address;
-        if opcode & 0x10 == 0 {
-            match (opcode >> 2) & 0x03 {
-                0x00 => {
-                    cycles = 2;
-                    address = self.immediate_address();
-                }
-                0x01 => {
-                    cycles = 3;
-                    address = self.zero_page_address();
-                }
-                0x02 => {
-                    cycles = 4;
-                    address = 0; // not used
-                }
-                0x03 => {
-                    cycles = 4;
-                    address = self.absolute_address();
-                }
-                _ => panic!("unknown control operation {:#x}", (opcode >> 2) & 0x03),
-            };
-        } else {
-            match (opcode >> 2) & 0x03 {
-                0x00 => {
-                    cycles = 2;
-                    address = self.relative_address();
-                }
-                0x01 => {
-                    cycles = 4;
-                    address = self.zero_page_indexed_address(Index::X);
-                }
-                0x02 => {
-                    cycles = 2;
-                    address = 0; // not used
-                }
-                0x03 => {
-                    cycles = 4;
-                    let result = self.absolute_indexed_address(Index::X);
-                    address = result.0;
-                    cycles += result.1;
-                }
-                _ => panic!("unknown operation {:#x}", (opcode >> 2) & 0x03),
-            };
-        }
-
-        (address, cycles)
+    /// Increases a value in memory by one, then subtracts it from the accumulator (INC + SBC)
+    fn isc(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.sbc(result);
+        result
     }
-
-    fn indexed_indirect_address(&mut self) -> u16 {
-        let value = self.memory.load(self.registers.program_counter);
-        let address = (value + self.registers.x) as u16;
-        self.registers.program_counter += 1;
-
-        self.load_word(address)
+    /// Stores the accumulator ANDed with the index x registry in memory. No flags are affected.
+    fn sax(&mut self) -> u8 {
+        self.registers.accumulator & self.registers.x
     }
-
-    fn indirect_indexed_address(&mut self) -> (u16, u8) {
-        let mut address = self.memory.load(self.registers.program_counter) as u16;
-        self.registers.program_counter += 1;
-
-        let low = self.memory.load(address);
-        let high = self.memory.load((address + 1) & 0x00ff);
-
-        address = ((high as u16) << 8) | (low as u16);
-
-        let result = address + (self.registers.y as u16);
-        let mut cycles = 0;
-
-        if !NesMemory::is_same_page(address, result) {
-            cycles += 1;
+    /// Loads a byte from memory into both the accumulator and the index x registry
+    fn lax(&mut self, value: u8) {
+        self.registers.set_zn(value);
+        self.registers.accumulator = value;
+        self.registers.x = value;
+    }
+    /// Immediate LAX. Unstable on real hardware: the accumulator is ORed with a chip-dependent
+    /// magic constant before the AND with the operand.
+    fn lxa(&mut self, value: u8) {
+        let result = (self.registers.accumulator | UNSTABLE_MAGIC) & value;
+        self.lax(result);
+    }
+    /// ANDs an immediate value into the accumulator, then copies the negative flag into carry
+    fn anc(&mut self, value: u8) {
+        self.and(value);
+        let negative = self.registers.get_flag(NEGATIVE_FLAG);
+        self.registers.set_flag(CARRY_FLAG, negative);
+    }
+    /// ANDs an immediate value into the accumulator, then shifts the accumulator right
+    /// (AND + LSR A)
+    fn alr(&mut self, value: u8) {
+        let a = self.registers.accumulator & value;
+        self.registers.accumulator = self.shift_right(a, false);
+    }
+    /// ANDs an immediate value into the accumulator, then rotates the accumulator right. Carry
+    /// is taken from bit 6 of the result and overflow from bit 6 XOR bit 5.
+    fn arr(&mut self, value: u8) {
+        let value = value & self.registers.accumulator;
+        let carry = self.registers.get_flag(CARRY_FLAG);
+        let mut result = value >> 1;
+        if carry {
+            result |= 0x80;
         }
 
-        (result, cycles)
+        self.registers.set_zn(result);
+        self.registers.set_flag(CARRY_FLAG, (result & 0x40) != 0);
+        self.registers.set_flag(OVERFLOW_FLAG, ((result >> 6) ^ (result >> 5)) & 1 != 0);
+        self.registers.accumulator = result;
     }
-
-    fn zero_page_address(&mut self) -> u16 {
-        let result = self.memory.load(self.registers.program_counter) as u16;
-        self.registers.program_counter += 1;
-
-        result
+    /// Subtracts an immediate value from the accumulator ANDed with the index x registry and
+    /// stores the result in x. Carry is set like CMP; the decimal flag and borrow are ignored.
+    fn axs(&mut self, value: u8) {
+        let ax = self.registers.accumulator & self.registers.x;
+        let result = ax.wrapping_sub(value);
+        self.registers.set_flag(CARRY_FLAG, ax >= value);
+        self.registers.set_zn(result);
+        self.registers.x = result;
     }
-
-    fn immediate_address(&mut self) -> u16 {
-        let result = self.registers.program_counter;
-        self.registers.program_counter += 1;
-        result
+    /// Unstable on real hardware: the accumulator is ORed with a chip-dependent magic constant,
+    /// then ANDed with the index x registry and an immediate value.
+    fn xaa(&mut self, value: u8) {
+        let result = (self.registers.accumulator | UNSTABLE_MAGIC) & self.registers.x & value;
+        self.registers.set_zn(result);
+        self.registers.accumulator = result;
     }
-
-    // Retrieves two bytes of memory, and increments the program counter twice. Returns the
-    // value interpreted as little endian
-    fn absolute_address(&mut self) -> u16 {
-        let pc = self.registers.program_counter;
-        println!("pc: {:x}", pc);
-        self.load_word(pc)
+    /// ANDs a byte of memory with the stack pointer and stores the result in the accumulator,
+    /// the index x registry and the stack pointer
+    fn las(&mut self, value: u8) {
+        let result = value & self.registers.stack_pointer;
+        self.lax(result);
+        self.registers.stack_pointer = result;
     }
-
-    // Adds the contents of the register pertaining to the supplied index to the address
-    // in the program counter, effectively using the supplied index register as an offset.
-    // Increments the program counter and returns the resulting address.
-    fn zero_page_indexed_address(&mut self, index: Index) -> u16 {
-        let value = self.memory.load(self.registers.program_counter);
-        let result = (value + self.registers.register_from_index(index)) as u16;
-        self.registers.program_counter += 1;
-
-        result
+    /// Stores the accumulator ANDed with the index x registry (see unstable_store())
+    fn ahx(&mut self) -> u8 {
+        self.registers.accumulator & self.registers.x
     }
-
-    // Add the contents of the register pertaining to index to an absolute address, effectively
-    // using the supplied index register as an offset. Also increments the program counter.
-    // Returns the resulting address and the number of cycles this operation should take.
-    fn absolute_indexed_address(&mut self, index: Index) -> (u16, u8) {
-        let pc = self.registers.program_counter;
-        let address = self.load_word(pc);
-        let result = address + (self.registers.register_from_index(index) as u16);
-
-        let mut cycles = 0;
-        if !NesMemory::is_same_page(address, result) {
-            cycles = 1;
-        }
-
-        (result, cycles)
+    /// Copies the accumulator ANDed with the index x registry into the stack pointer, then stores
+    /// it (see unstable_store())
+    fn tas(&mut self) -> u8 {
+        let value = self.registers.accumulator & self.registers.x;
+        self.registers.stack_pointer = value;
+        value
     }
-
-    fn relative_address(&mut self) -> u16 {
-        let value = self.memory.load(self.registers.program_counter) as i8;
-        self.registers.program_counter += 1;
-        let offset = if value > 0x7f {
-            // 127
-            !(0x0100 - value)
-        } else {
-            value
-        };
-
-        let result = ((self.registers.program_counter as i32) + (offset as i32)) as u16;
-        result
+    /// Stores the index y registry (see unstable_store())
+    fn shy(&mut self) -> u8 {
+        self.registers.y
     }
-
-    fn indirect_address(&mut self) -> u16 {
-        let pc = self.registers.program_counter;
-        let addr = self.load_word(pc);
-        self.registers.program_counter += 2;
-
-        // 6502 has a bug where it only increments the high byte instead
-        // of the entire 16-bit address.
-        let low_val = self.memory.load(addr);
-        let high_val = self.memory.load((addr & 0xff00) | ((addr + 1) & 0x00ff));
-
-        (high_val as u16) << 8 | low_val as u16
+    /// Stores the index x registry (see unstable_store())
+    fn shx(&mut self) -> u8 {
+        self.registers.x
     }
 
     // interrupts
 
+    /// Drives an interrupt line. NMI fires once each time its line is asserted, IRQ keeps firing
+    /// for as long as its line is held and interrupts are enabled, and asserting RST requests a
+    /// single reset sequence. IRQ is driven as IrqSource::External, devices with their own
+    /// source should use the irq line directly.
     pub fn interrupt(&mut self, interrupt_type: Interrupt, state: bool) {
         match interrupt_type {
-            Interrupt::Irq => self.irq = state,
-            Interrupt::Nmi => self.nmi = state,
+            Interrupt::Irq => self.irq.set(IrqSource::External, state),
+            Interrupt::Nmi => {
+                if state && !self.nmi {
+                    self.nmi_pending = true;
+                }
+                self.nmi = state;
+            }
             Interrupt::Rst => self.rst = state,
         }
     }
 
+    /// Returns the level of an interrupt line (for RST, whether a reset is still to be done)
     pub fn get_interrupt(&self, interrupt_type: Interrupt) -> bool {
-        let result = match interrupt_type {
-            Interrupt::Irq => self.irq,
+        match interrupt_type {
+            Interrupt::Irq => self.irq.is_asserted(),
             Interrupt::Nmi => self.nmi,
             Interrupt::Rst => self.rst,
-        };
-        result
+        }
+    }
+
+    /// Services the highest priority pending interrupt, if any, and returns the number of cycles
+    /// spent doing so. Does nothing part way through an instruction.
+    pub fn do_interrupts(&mut self) -> u8 {
+        if self.step != 0 || !self.interrupt_pending() {
+            return 0;
+        }
+
+        let start = self.cycle;
+        self.tick();
+        while self.step != 0 {
+            self.tick();
+        }
+
+        (self.cycle - start) as u8
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.rst ||
+            (!self.is_jammed() &&
+                (self.nmi_pending || (self.irq.is_asserted() && !self.irq_masked())))
     }
 
-    pub fn do_interrupts(&mut self) {
-        if self.irq && !self.registers.get_flag(INTERRUPT_FLAG) {
-            self.do_irq();
-            self.irq = false;
-        } else if self.nmi {
-            self.do_nmi();
-            self.nmi = false;
-        } else if self.rst {
-            self.do_rst();
+    /// Whether IRQs are ignored by the next poll
+    fn irq_masked(&self) -> bool {
+        self.delayed_interrupt_flag
+            .unwrap_or_else(|| self.registers.get_flag(INTERRUPT_FLAG))
+    }
+
+    /// Returns (and acknowledges) the highest priority pending interrupt. IRQ isn't acknowledged,
+    /// the device that raised it has to release the line.
+    fn pending_interrupt(&mut self) -> Option<Interrupt> {
+        if self.rst {
             self.rst = false;
+            Some(Interrupt::Rst)
+        } else if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(Interrupt::Nmi)
+        } else if self.irq.is_asserted() && !self.irq_masked() {
+            Some(Interrupt::Irq)
+        } else {
+            None
         }
     }
+}
 
-    fn do_irq(&mut self) {
-        let pc = self.registers.program_counter;
-        self.push_word(pc);
-        let status = self.registers.processor_status;
-        self.push(status);
+/// A decoded opcode: how its operand is addressed and what is done with it
+#[derive(Debug)]
+struct Instruction<M: Memory> {
+    opcode: u8,
+    mode: AddressingMode,
+    operation: Operation<M>,
+}
 
-        let addr = self.load_word(0xffff_fffe);
-        self.registers.program_counter = addr;
+impl<M: Memory> Clone for Instruction<M> {
+    fn clone(&self) -> Self {
+        *self
     }
+}
 
-    fn do_nmi(&mut self) {
-        let pc = self.registers.program_counter;
-        self.push_word(pc);
-        let status = self.registers.processor_status;
-        self.push(status);
+impl<M: Memory> Copy for Instruction<M> {}
 
-        let addr = self.load_word(0xfffb_fffa);
-        self.registers.program_counter = addr;
+impl<M: Memory> Instruction<M> {
+    fn is_interrupt(&self) -> bool {
+        matches!(self.operation, Operation::Interrupt(_))
     }
+}
+
+/// What an instruction does with its operand once it has been addressed
+#[derive(Debug)]
+enum Operation<M: Memory> {
+    /// reads the operand
+    Read(fn(&mut Cpu<M>, u8)),
+    /// writes the returned value to the operand
+    Write(fn(&mut Cpu<M>) -> u8),
+    /// reads the operand, writes it back unmodified and then writes the returned value
+    Modify(fn(&mut Cpu<M>, u8) -> u8),
+    /// AHX/TAS/SHY/SHX, whose written value and address depend on the address high byte
+    UnstableStore(fn(&mut Cpu<M>) -> u8),
+    /// operates on registers only
+    Implied(fn(&mut Cpu<M>)),
+    /// branches when the returned condition holds
+    Branch(fn(&Cpu<M>) -> bool),
+    /// pushes the returned value
+    Push(fn(&mut Cpu<M>) -> u8),
+    /// pulls a value from the stack
+    Pull(fn(&mut Cpu<M>, u8)),
+    Jmp,
+    Jsr,
+    Rts,
+    Rti,
+    Brk,
+    /// halts the cpu until reset
+    Kil,
+    /// the 7 cycle IRQ/NMI/reset sequence
+    Interrupt(Interrupt),
+}
 
-    fn do_rst(&mut self) {
-        let addr = self.load_word(0xfffd_fffc);
-        self.registers.program_counter = addr;
+impl<M: Memory> Clone for Operation<M> {
+    fn clone(&self) -> Self {
+        *self
     }
 }
 
+impl<M: Memory> Copy for Operation<M> {}
+
 #[derive(Default, Debug)]
 pub struct Registers {
     /// (A) Accumulator, arithmetic/logic instructions
@@ -1463,15 +1667,73 @@ impl Registers {
     }
 }
 
-#[derive(Debug)]
-enum Index {
-    X,
-    Y,
+/// Whether the CPU is executing instructions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CpuState {
+    Running,
+    /// a KIL opcode halted the CPU. It stays halted, ignoring NMI and IRQ, until reset.
+    Jammed { opcode: u8, address: u16 },
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Interrupt {
     Irq,
     Nmi,
     Rst,
 }
+
+/// Devices that can pull the IRQ line
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IrqSource {
+    /// anything driving the line through Cpu::interrupt()
+    External,
+    /// APU frame counter
+    FrameCounter,
+    /// APU delta modulation channel
+    Dmc,
+    /// cartridge, e.g. a scanline counter
+    Mapper,
+}
+
+impl IrqSource {
+    fn mask(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// The IRQ line is shared by every source able to raise an interrupt and is asserted for as long
+/// as any of them holds it (wired-OR). Each source asserts and acknowledges independently, so
+/// one device releasing the line doesn't hide another's pending interrupt.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IrqLine {
+    sources: u8,
+}
+
+impl IrqLine {
+    pub fn assert(&mut self, source: IrqSource) {
+        self.sources |= source.mask();
+    }
+
+    /// Releases the line on behalf of source, as done when the device's interrupt is
+    /// acknowledged
+    pub fn acknowledge(&mut self, source: IrqSource) {
+        self.sources &= !source.mask();
+    }
+
+    pub fn set(&mut self, source: IrqSource, asserted: bool) {
+        if asserted {
+            self.assert(source);
+        } else {
+            self.acknowledge(source);
+        }
+    }
+
+    /// Whether the line is pulled by any source
+    pub fn is_asserted(&self) -> bool {
+        self.sources != 0
+    }
+
+    pub fn is_asserted_by(&self, source: IrqSource) -> bool {
+        self.sources & source.mask() != 0
+    }
+}
@@ -0,0 +1,418 @@
+//! Opcode table and disassembler for the 6502
+
+use self::AddressingMode::*;
+use self::Index::*;
+use super::memory::Memory;
+
+/// Register used to index an address
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Index {
+    X,
+    Y,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageIndexed(Index),
+    Absolute,
+    AbsoluteIndexed(Index),
+    /// (zp,X)
+    IndexedIndirect,
+    /// (zp),Y
+    IndirectIndexed,
+    Relative,
+    Indirect,
+}
+
+impl AddressingMode {
+    /// number of bytes taken by an instruction using this mode, including the opcode
+    pub const fn length(&self) -> u8 {
+        match *self {
+            Implied | Accumulator => 1,
+            Absolute | AbsoluteIndexed(_) | Indirect => 3,
+            _ => 2,
+        }
+    }
+}
+
+/// Static description of an opcode
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// number of bytes taken by the instruction, including the opcode
+    pub length: u8,
+    /// cycles taken without page crossing or a taken branch. 0 for the KIL opcodes, which jam
+    /// the cpu.
+    pub cycles: u8,
+    /// false for the opcodes that aren't part of the documented 6502 instruction set
+    pub official: bool,
+}
+
+const fn opcode(
+    mnemonic: &'static str,
+    mode: AddressingMode,
+    cycles: u8,
+    official: bool,
+) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        length: mode.length(),
+        cycles,
+        official,
+    }
+}
+
+/// Every opcode, indexed by its value
+pub static OPCODES: [Opcode; 256] = [
+    // 0x00
+    opcode("BRK", Implied, 7, true),
+    opcode("ORA", IndexedIndirect, 6, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("SLO", IndexedIndirect, 8, false),
+    opcode("NOP", ZeroPage, 3, false),
+    opcode("ORA", ZeroPage, 3, true),
+    opcode("ASL", ZeroPage, 5, true),
+    opcode("SLO", ZeroPage, 5, false),
+    opcode("PHP", Implied, 3, true),
+    opcode("ORA", Immediate, 2, true),
+    opcode("ASL", Accumulator, 2, true),
+    opcode("ANC", Immediate, 2, false),
+    opcode("NOP", Absolute, 4, false),
+    opcode("ORA", Absolute, 4, true),
+    opcode("ASL", Absolute, 6, true),
+    opcode("SLO", Absolute, 6, false),
+
+    // 0x10
+    opcode("BPL", Relative, 2, true),
+    opcode("ORA", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("SLO", IndirectIndexed, 8, false),
+    opcode("NOP", ZeroPageIndexed(X), 4, false),
+    opcode("ORA", ZeroPageIndexed(X), 4, true),
+    opcode("ASL", ZeroPageIndexed(X), 6, true),
+    opcode("SLO", ZeroPageIndexed(X), 6, false),
+    opcode("CLC", Implied, 2, true),
+    opcode("ORA", AbsoluteIndexed(Y), 4, true),
+    opcode("NOP", Implied, 2, false),
+    opcode("SLO", AbsoluteIndexed(Y), 7, false),
+    opcode("NOP", AbsoluteIndexed(X), 4, false),
+    opcode("ORA", AbsoluteIndexed(X), 4, true),
+    opcode("ASL", AbsoluteIndexed(X), 7, true),
+    opcode("SLO", AbsoluteIndexed(X), 7, false),
+
+    // 0x20
+    opcode("JSR", Absolute, 6, true),
+    opcode("AND", IndexedIndirect, 6, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("RLA", IndexedIndirect, 8, false),
+    opcode("BIT", ZeroPage, 3, true),
+    opcode("AND", ZeroPage, 3, true),
+    opcode("ROL", ZeroPage, 5, true),
+    opcode("RLA", ZeroPage, 5, false),
+    opcode("PLP", Implied, 4, true),
+    opcode("AND", Immediate, 2, true),
+    opcode("ROL", Accumulator, 2, true),
+    opcode("ANC", Immediate, 2, false),
+    opcode("BIT", Absolute, 4, true),
+    opcode("AND", Absolute, 4, true),
+    opcode("ROL", Absolute, 6, true),
+    opcode("RLA", Absolute, 6, false),
+
+    // 0x30
+    opcode("BMI", Relative, 2, true),
+    opcode("AND", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("RLA", IndirectIndexed, 8, false),
+    opcode("NOP", ZeroPageIndexed(X), 4, false),
+    opcode("AND", ZeroPageIndexed(X), 4, true),
+    opcode("ROL", ZeroPageIndexed(X), 6, true),
+    opcode("RLA", ZeroPageIndexed(X), 6, false),
+    opcode("SEC", Implied, 2, true),
+    opcode("AND", AbsoluteIndexed(Y), 4, true),
+    opcode("NOP", Implied, 2, false),
+    opcode("RLA", AbsoluteIndexed(Y), 7, false),
+    opcode("NOP", AbsoluteIndexed(X), 4, false),
+    opcode("AND", AbsoluteIndexed(X), 4, true),
+    opcode("ROL", AbsoluteIndexed(X), 7, true),
+    opcode("RLA", AbsoluteIndexed(X), 7, false),
+
+    // 0x40
+    opcode("RTI", Implied, 6, true),
+    opcode("EOR", IndexedIndirect, 6, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("SRE", IndexedIndirect, 8, false),
+    opcode("NOP", ZeroPage, 3, false),
+    opcode("EOR", ZeroPage, 3, true),
+    opcode("LSR", ZeroPage, 5, true),
+    opcode("SRE", ZeroPage, 5, false),
+    opcode("PHA", Implied, 3, true),
+    opcode("EOR", Immediate, 2, true),
+    opcode("LSR", Accumulator, 2, true),
+    opcode("ALR", Immediate, 2, false),
+    opcode("JMP", Absolute, 3, true),
+    opcode("EOR", Absolute, 4, true),
+    opcode("LSR", Absolute, 6, true),
+    opcode("SRE", Absolute, 6, false),
+
+    // 0x50
+    opcode("BVC", Relative, 2, true),
+    opcode("EOR", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("SRE", IndirectIndexed, 8, false),
+    opcode("NOP", ZeroPageIndexed(X), 4, false),
+    opcode("EOR", ZeroPageIndexed(X), 4, true),
+    opcode("LSR", ZeroPageIndexed(X), 6, true),
+    opcode("SRE", ZeroPageIndexed(X), 6, false),
+    opcode("CLI", Implied, 2, true),
+    opcode("EOR", AbsoluteIndexed(Y), 4, true),
+    opcode("NOP", Implied, 2, false),
+    opcode("SRE", AbsoluteIndexed(Y), 7, false),
+    opcode("NOP", AbsoluteIndexed(X), 4, false),
+    opcode("EOR", AbsoluteIndexed(X), 4, true),
+    opcode("LSR", AbsoluteIndexed(X), 7, true),
+    opcode("SRE", AbsoluteIndexed(X), 7, false),
+
+    // 0x60
+    opcode("RTS", Implied, 6, true),
+    opcode("ADC", IndexedIndirect, 6, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("RRA", IndexedIndirect, 8, false),
+    opcode("NOP", ZeroPage, 3, false),
+    opcode("ADC", ZeroPage, 3, true),
+    opcode("ROR", ZeroPage, 5, true),
+    opcode("RRA", ZeroPage, 5, false),
+    opcode("PLA", Implied, 4, true),
+    opcode("ADC", Immediate, 2, true),
+    opcode("ROR", Accumulator, 2, true),
+    opcode("ARR", Immediate, 2, false),
+    opcode("JMP", Indirect, 5, true),
+    opcode("ADC", Absolute, 4, true),
+    opcode("ROR", Absolute, 6, true),
+    opcode("RRA", Absolute, 6, false),
+
+    // 0x70
+    opcode("BVS", Relative, 2, true),
+    opcode("ADC", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("RRA", IndirectIndexed, 8, false),
+    opcode("NOP", ZeroPageIndexed(X), 4, false),
+    opcode("ADC", ZeroPageIndexed(X), 4, true),
+    opcode("ROR", ZeroPageIndexed(X), 6, true),
+    opcode("RRA", ZeroPageIndexed(X), 6, false),
+    opcode("SEI", Implied, 2, true),
+    opcode("ADC", AbsoluteIndexed(Y), 4, true),
+    opcode("NOP", Implied, 2, false),
+    opcode("RRA", AbsoluteIndexed(Y), 7, false),
+    opcode("NOP", AbsoluteIndexed(X), 4, false),
+    opcode("ADC", AbsoluteIndexed(X), 4, true),
+    opcode("ROR", AbsoluteIndexed(X), 7, true),
+    opcode("RRA", AbsoluteIndexed(X), 7, false),
+
+    // 0x80
+    opcode("NOP", Immediate, 2, false),
+    opcode("STA", IndexedIndirect, 6, true),
+    opcode("NOP", Immediate, 2, false),
+    opcode("SAX", IndexedIndirect, 6, false),
+    opcode("STY", ZeroPage, 3, true),
+    opcode("STA", ZeroPage, 3, true),
+    opcode("STX", ZeroPage, 3, true),
+    opcode("SAX", ZeroPage, 3, false),
+    opcode("DEY", Implied, 2, true),
+    opcode("NOP", Immediate, 2, false),
+    opcode("TXA", Implied, 2, true),
+    opcode("XAA", Immediate, 2, false),
+    opcode("STY", Absolute, 4, true),
+    opcode("STA", Absolute, 4, true),
+    opcode("STX", Absolute, 4, true),
+    opcode("SAX", Absolute, 4, false),
+
+    // 0x90
+    opcode("BCC", Relative, 2, true),
+    opcode("STA", IndirectIndexed, 6, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("AHX", IndirectIndexed, 6, false),
+    opcode("STY", ZeroPageIndexed(X), 4, true),
+    opcode("STA", ZeroPageIndexed(X), 4, true),
+    opcode("STX", ZeroPageIndexed(Y), 4, true),
+    opcode("SAX", ZeroPageIndexed(Y), 4, false),
+    opcode("TYA", Implied, 2, true),
+    opcode("STA", AbsoluteIndexed(Y), 5, true),
+    opcode("TXS", Implied, 2, true),
+    opcode("TAS", AbsoluteIndexed(Y), 5, false),
+    opcode("SHY", AbsoluteIndexed(X), 5, false),
+    opcode("STA", AbsoluteIndexed(X), 5, true),
+    opcode("SHX", AbsoluteIndexed(Y), 5, false),
+    opcode("AHX", AbsoluteIndexed(Y), 5, false),
+
+    // 0xa0
+    opcode("LDY", Immediate, 2, true),
+    opcode("LDA", IndexedIndirect, 6, true),
+    opcode("LDX", Immediate, 2, true),
+    opcode("LAX", IndexedIndirect, 6, false),
+    opcode("LDY", ZeroPage, 3, true),
+    opcode("LDA", ZeroPage, 3, true),
+    opcode("LDX", ZeroPage, 3, true),
+    opcode("LAX", ZeroPage, 3, false),
+    opcode("TAY", Implied, 2, true),
+    opcode("LDA", Immediate, 2, true),
+    opcode("TAX", Implied, 2, true),
+    opcode("LAX", Immediate, 2, false),
+    opcode("LDY", Absolute, 4, true),
+    opcode("LDA", Absolute, 4, true),
+    opcode("LDX", Absolute, 4, true),
+    opcode("LAX", Absolute, 4, false),
+
+    // 0xb0
+    opcode("BCS", Relative, 2, true),
+    opcode("LDA", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("LAX", IndirectIndexed, 5, false),
+    opcode("LDY", ZeroPageIndexed(X), 4, true),
+    opcode("LDA", ZeroPageIndexed(X), 4, true),
+    opcode("LDX", ZeroPageIndexed(Y), 4, true),
+    opcode("LAX", ZeroPageIndexed(Y), 4, false),
+    opcode("CLV", Implied, 2, true),
+    opcode("LDA", AbsoluteIndexed(Y), 4, true),
+    opcode("TSX", Implied, 2, true),
+    opcode("LAS", AbsoluteIndexed(Y), 4, false),
+    opcode("LDY", AbsoluteIndexed(X), 4, true),
+    opcode("LDA", AbsoluteIndexed(X), 4, true),
+    opcode("LDX", AbsoluteIndexed(Y), 4, true),
+    opcode("LAX", AbsoluteIndexed(Y), 4, false),
+
+    // 0xc0
+    opcode("CPY", Immediate, 2, true),
+    opcode("CMP", IndexedIndirect, 6, true),
+    opcode("NOP", Immediate, 2, false),
+    opcode("DCP", IndexedIndirect, 8, false),
+    opcode("CPY", ZeroPage, 3, true),
+    opcode("CMP", ZeroPage, 3, true),
+    opcode("DEC", ZeroPage, 5, true),
+    opcode("DCP", ZeroPage, 5, false),
+    opcode("INY", Implied, 2, true),
+    opcode("CMP", Immediate, 2, true),
+    opcode("DEX", Implied, 2, true),
+    opcode("AXS", Immediate, 2, false),
+    opcode("CPY", Absolute, 4, true),
+    opcode("CMP", Absolute, 4, true),
+    opcode("DEC", Absolute, 6, true),
+    opcode("DCP", Absolute, 6, false),
+
+    // 0xd0
+    opcode("BNE", Relative, 2, true),
+    opcode("CMP", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("DCP", IndirectIndexed, 8, false),
+    opcode("NOP", ZeroPageIndexed(X), 4, false),
+    opcode("CMP", ZeroPageIndexed(X), 4, true),
+    opcode("DEC", ZeroPageIndexed(X), 6, true),
+    opcode("DCP", ZeroPageIndexed(X), 6, false),
+    opcode("CLD", Implied, 2, true),
+    opcode("CMP", AbsoluteIndexed(Y), 4, true),
+    opcode("NOP", Implied, 2, false),
+    opcode("DCP", AbsoluteIndexed(Y), 7, false),
+    opcode("NOP", AbsoluteIndexed(X), 4, false),
+    opcode("CMP", AbsoluteIndexed(X), 4, true),
+    opcode("DEC", AbsoluteIndexed(X), 7, true),
+    opcode("DCP", AbsoluteIndexed(X), 7, false),
+
+    // 0xe0
+    opcode("CPX", Immediate, 2, true),
+    opcode("SBC", IndexedIndirect, 6, true),
+    opcode("NOP", Immediate, 2, false),
+    opcode("ISC", IndexedIndirect, 8, false),
+    opcode("CPX", ZeroPage, 3, true),
+    opcode("SBC", ZeroPage, 3, true),
+    opcode("INC", ZeroPage, 5, true),
+    opcode("ISC", ZeroPage, 5, false),
+    opcode("INX", Implied, 2, true),
+    opcode("SBC", Immediate, 2, true),
+    opcode("NOP", Implied, 2, true),
+    opcode("SBC", Immediate, 2, false),
+    opcode("CPX", Absolute, 4, true),
+    opcode("SBC", Absolute, 4, true),
+    opcode("INC", Absolute, 6, true),
+    opcode("ISC", Absolute, 6, false),
+
+    // 0xf0
+    opcode("BEQ", Relative, 2, true),
+    opcode("SBC", IndirectIndexed, 5, true),
+    opcode("KIL", Implied, 0, false),
+    opcode("ISC", IndirectIndexed, 8, false),
+    opcode("NOP", ZeroPageIndexed(X), 4, false),
+    opcode("SBC", ZeroPageIndexed(X), 4, true),
+    opcode("INC", ZeroPageIndexed(X), 6, true),
+    opcode("ISC", ZeroPageIndexed(X), 6, false),
+    opcode("SED", Implied, 2, true),
+    opcode("SBC", AbsoluteIndexed(Y), 4, true),
+    opcode("NOP", Implied, 2, false),
+    opcode("ISC", AbsoluteIndexed(Y), 7, false),
+    opcode("NOP", AbsoluteIndexed(X), 4, false),
+    opcode("SBC", AbsoluteIndexed(X), 4, true),
+    opcode("INC", AbsoluteIndexed(X), 7, true),
+    opcode("ISC", AbsoluteIndexed(X), 7, false),
+];
+
+/// Disassembles the instruction at address, returning its text and length in bytes. Branch
+/// targets are resolved to absolute addresses. Memory is only peeked, so this has no effect on
+/// the bus.
+pub fn disassemble_instruction<M: Memory>(memory: &M, address: u16) -> (String, u8) {
+    let opcode = &OPCODES[memory.peek(address) as usize];
+    let byte = memory.peek(address.wrapping_add(1));
+    let word = (memory.peek(address.wrapping_add(2)) as u16) << 8 | byte as u16;
+
+    let operand = match opcode.mode {
+        Implied => String::new(),
+        Accumulator => "A".to_string(),
+        Immediate => format!("#${:02X}", byte),
+        ZeroPage => format!("${:02X}", byte),
+        ZeroPageIndexed(index) => format!("${:02X},{:?}", byte, index),
+        Absolute => format!("${:04X}", word),
+        AbsoluteIndexed(index) => format!("${:04X},{:?}", word, index),
+        IndexedIndirect => format!("(${:02X},X)", byte),
+        IndirectIndexed => format!("(${:02X}),Y", byte),
+        Relative => format!("${:04X}", branch_target(address, byte)),
+        Indirect => format!("(${:04X})", word),
+    };
+
+    let text = if operand.is_empty() {
+        opcode.mnemonic.to_string()
+    } else {
+        format!("{} {}", opcode.mnemonic, operand)
+    };
+
+    (text, opcode.length)
+}
+
+/// Disassembles the instructions from start up to and including end, one per line, in the form
+/// `C000  4C F5 C5  JMP $C5F5`. Unofficial opcodes are marked with a '*'.
+pub fn disassemble<M: Memory>(memory: &M, start: u16, end: u16) -> String {
+    let mut out = String::new();
+    let mut address = start as u32;
+
+    while address <= end as u32 {
+        let pc = address as u16;
+        let (text, length) = disassemble_instruction(memory, pc);
+        let bytes = (0..length)
+            .map(|i| format!("{:02X}", memory.peek(pc.wrapping_add(i as u16))))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let marker = if OPCODES[memory.peek(pc) as usize].official { " " } else { "*" };
+
+        out.push_str(&format!("{:04X}  {:<8} {}{}\n", pc, bytes, marker, text));
+        address += length as u32;
+    }
+
+    out
+}
+
+/// Address a branch at address with the given offset jumps to when taken
+pub fn branch_target(address: u16, offset: u8) -> u16 {
+    address.wrapping_add(2).wrapping_add(offset as i8 as u16)
+}
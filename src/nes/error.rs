@@ -0,0 +1,69 @@
+use super::mapper::mapper_name;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// Everything that can go wrong while loading a cartridge or running the emulator
+#[derive(Debug)]
+pub enum Error {
+    /// the file doesn't start with the iNES or UNIF magic, or a UNIF image names no board or
+    /// has no PRG-ROM
+    InvalidHeader,
+    /// the file ended before all of the data its header announces
+    Truncated,
+    UnsupportedMapper(u16),
+    /// PRG-ROM of a size the mapper can't map: none at all, or not a multiple of 16K
+    InvalidPrgRomSize(usize),
+    /// a UNIF board name that doesn't correspond to any mapper number
+    UnsupportedBoard(String),
+    /// a Famicom Disk System image was loaded without the BIOS
+    MissingBios,
+    /// the disk has no such side
+    NoSuchDiskSide(usize),
+    /// a KIL opcode halted the CPU
+    CpuJam { opcode: u8, address: u16 },
+    Io(io::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> result::Result<(), fmt::Error> {
+        match *self {
+            Error::InvalidHeader => write!(f, "invalid header magic"),
+            Error::Truncated => write!(f, "file ended prematurely"),
+            Error::UnsupportedMapper(id) => match mapper_name(id) {
+                Some(name) => write!(f, "unsupported mapper {} ({})", id, name),
+                None => write!(f, "unsupported mapper {}", id),
+            },
+            Error::InvalidPrgRomSize(0) => write!(f, "no PRG-ROM"),
+            Error::InvalidPrgRomSize(size) => {
+                write!(f, "PRG-ROM of {} bytes isn't a multiple of 16K", size)
+            }
+            Error::UnsupportedBoard(ref board) => write!(f, "unsupported UNIF board {}", board),
+            Error::MissingBios => write!(f, "disk images need the FDS BIOS (disksys.rom)"),
+            Error::NoSuchDiskSide(side) => write!(f, "the disk has no side {}", side),
+            Error::CpuJam { opcode, address } => {
+                write!(f, "cpu jammed by opcode {:02x} at {:04x}", opcode, address)
+            }
+            Error::Io(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,478 @@
+use super::error::{Error, Result};
+use super::mapper::{Mapper, MapperResult, Mirroring};
+use super::rom::{read_to_buf, NesHeader, Rom};
+use std::io::Read;
+
+/// fwNES header: "FDS\x1a", the number of sides and 11 zero bytes
+pub const MAGIC: &[u8; 4] = b"FDS\x1a";
+const HEADER_SIZE: usize = 16;
+/// every side starts with a disk info block holding this
+const DISK_VERIFICATION: &[u8; 15] = b"\x01*NINTENDO-HVC*";
+/// bytes per side in an .fds image
+pub const SIDE_SIZE: usize = 65500;
+/// disksys.rom, mapped at $E000-$FFFF
+pub const BIOS_SIZE: usize = 8192;
+
+/// gap before the first block on a side, 28300 bits
+const LEAD_IN_GAP: usize = 28300 / 8;
+/// gap after every block, 976 bits
+const BLOCK_GAP: usize = 976 / 8;
+/// CPU cycles from the motor starting to the head reaching the first gap
+const HEAD_DELAY: u32 = 50000;
+/// CPU cycles per byte going past the head, at 96.4 kbit/s
+const BYTE_DELAY: u32 = 150;
+/// CPU cycles a newly inserted side takes to show up, the BIOS only notices a side change
+/// after seeing the drive empty
+const INSERT_DELAY: u32 = 1_000_000;
+
+/// $4025
+const CTRL_MOTOR: u8 = 0x01;
+const CTRL_TRANSFER_RESET: u8 = 0x02;
+const CTRL_READ: u8 = 0x04;
+const CTRL_HORIZONTAL: u8 = 0x08;
+const CTRL_CRC: u8 = 0x10;
+const CTRL_TRANSFER: u8 = 0x40;
+const CTRL_IRQ: u8 = 0x80;
+
+/// Whether data starts like a disk image, with or without the fwNES header
+pub fn is_disk_image(start: &[u8]) -> bool {
+    start.starts_with(MAGIC) || start.starts_with(DISK_VERIFICATION)
+}
+
+/// Loads an .fds disk image along with the BIOS it runs on
+///
+/// The header of the ROM is made up: NES 2.0 mapper 20, the BIOS as 8K of PRG-ROM, 32K of
+/// PRG-RAM and 8K of CHR-RAM.
+pub fn load(disk: &mut dyn Read, bios: &mut dyn Read) -> Result<Rom> {
+    let disk = Disk::load(disk)?;
+    let mut prg = vec![0u8; BIOS_SIZE];
+    read_to_buf(&mut prg, bios)?;
+
+    let header = NesHeader {
+        magic: *b"NES\x1a",
+        // 2^13 bytes
+        prg_rom_size: 13 << 2,
+        chr_rom_size: 0,
+        flags_6: (20 & 0x0f) << 4,
+        flags_7: (20 & 0xf0) | 0x08,
+        flags_8: 0,
+        flags_9: 0x0f,
+        // 64 << 9 and 64 << 7 bytes
+        flags_10: 9,
+        flags_11: 7,
+        flags_12: 0,
+        flags_13: 0,
+        flags_14: 0,
+        flags_15: 0,
+    };
+
+    Ok(Rom {
+        header,
+        board: None,
+        trainer: None,
+        prg,
+        chr: Vec::new(),
+        disk: Some(disk),
+    })
+}
+
+/// Disk sides as an .fds image has them: the blocks one after another, without the gaps and
+/// CRCs on a real disk
+#[derive(Debug)]
+pub struct Disk {
+    pub sides: Vec<Vec<u8>>,
+}
+
+impl Disk {
+    pub fn load(r: &mut dyn Read) -> Result<Disk> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+
+        // the header's side count can't be trusted, go by the size instead
+        let start = if data.starts_with(MAGIC) { HEADER_SIZE } else { 0 };
+        if data.len() <= start {
+            return Err(Error::Truncated);
+        } else if !data[start..].starts_with(DISK_VERIFICATION) {
+            return Err(Error::InvalidHeader);
+        }
+
+        let sides = data[start..]
+            .chunks(SIDE_SIZE)
+            .map(|side| {
+                // some dumps drop the zeros at the end of the last side
+                let mut side = side.to_vec();
+                side.resize(SIDE_SIZE, 0);
+                side
+            })
+            .collect();
+
+        Ok(Disk { sides })
+    }
+}
+
+/// Lays a side out the way the drive sees it: a long gap, then every block with a start mark
+/// before it, its CRC and another gap after it. Blocks are found by walking the file
+/// structure, the disk info and file amount blocks, then a header and data block per file.
+///
+/// See: http://wiki.nesdev.com/w/index.php/FDS_disk_format
+fn raw_side(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0u8; LEAD_IN_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+
+    while position < side.len() {
+        let len = match side[position] {
+            1 => 56,
+            2 => 2,
+            3 if position + 16 <= side.len() => {
+                file_size = side[position + 13] as usize | (side[position + 14] as usize) << 8;
+                16
+            }
+            4 => file_size + 1,
+            _ => break,
+        };
+        let end = side.len().min(position + len);
+
+        raw.push(0x80);
+        raw.extend_from_slice(&side[position..end]);
+        // the CRC is never checked
+        raw.extend_from_slice(&[0x4d, 0x62]);
+        raw.extend(vec![0u8; BLOCK_GAP]);
+        position = end;
+    }
+
+    raw
+}
+
+/// Famicom Disk System RAM adapter (mapper 20)
+///
+/// 32K of PRG-RAM at $6000-$DFFF, the BIOS at $E000-$FFFF, 8K of CHR-RAM, a timer IRQ and the
+/// disk drive. The drive is emulated a byte at a time; writes change the disk in memory only,
+/// and the expansion audio isn't emulated.
+///
+/// See: http://wiki.nesdev.com/w/index.php/Family_Computer_Disk_System
+pub struct Fds {
+    pub rom: Box<Rom>,
+    prg_ram: Vec<u8>,
+    chr_ram: Box<[u8; 8192]>,
+    /// every side as the drive sees it
+    sides: Vec<Vec<u8>>,
+    /// inserted side
+    side: Option<usize>,
+    /// cycles until an inserted side shows up
+    insert_delay: u32,
+
+    /// $4023 bit 0
+    disk_registers: bool,
+    /// $4020/$4021
+    timer_reload: u16,
+    timer_counter: u16,
+    /// $4022
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    /// $4025
+    ctrl: u8,
+    /// $4024
+    write_data: u8,
+    /// $4031
+    read_data: u8,
+    /// a byte was transferred, $4030 bit 1
+    transfer_complete: bool,
+    disk_irq: bool,
+    /// position of the head on the side
+    position: usize,
+    /// cycles until the next byte reaches the head
+    delay: u32,
+    /// the head is back at the start of the side, $4030 bit 6
+    end_of_head: bool,
+    scanning: bool,
+    /// the start mark of the current block went by
+    gap_ended: bool,
+}
+
+impl Fds {
+    pub fn new(mut rom: Box<Rom>) -> Fds {
+        let sides = match rom.disk.take() {
+            Some(disk) => disk.sides.iter().map(|side| raw_side(side)).collect(),
+            None => Vec::new(),
+        };
+        let prg_ram = vec![0; rom.header.prg_ram_bytes()];
+
+        let mut fds = Fds {
+            rom,
+            prg_ram,
+            chr_ram: Box::new([0; 8192]),
+            side: if sides.is_empty() { None } else { Some(0) },
+            sides,
+            insert_delay: 0,
+            disk_registers: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            ctrl: 0,
+            write_data: 0,
+            read_data: 0,
+            transfer_complete: false,
+            disk_irq: false,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+        };
+        fds.power_on();
+
+        fds
+    }
+
+    fn disk_inserted(&self) -> bool {
+        self.side.is_some() && self.insert_delay == 0
+    }
+
+    fn tick_timer(&mut self) {
+        if !self.timer_enabled {
+            return;
+        }
+
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    /// Moves the disk along under the head
+    fn tick_drive(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+            return;
+        }
+
+        let side = match self.side {
+            Some(side) if self.ctrl & CTRL_MOTOR != 0 => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+
+        if self.ctrl & CTRL_TRANSFER_RESET != 0 && !self.scanning {
+            return;
+        }
+
+        if self.end_of_head {
+            self.delay = HEAD_DELAY;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let ready = self.ctrl & CTRL_TRANSFER != 0;
+        let irq = self.ctrl & CTRL_IRQ != 0;
+        if self.ctrl & CTRL_READ != 0 {
+            let data = self.sides[side].get(self.position).cloned().unwrap_or(0);
+            let mut irq = irq;
+            if !ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // the start mark is transferred, but without an IRQ
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.read_data = data;
+                self.transfer_complete = true;
+                self.disk_irq |= irq;
+            }
+        } else {
+            // the CRC already on the disk stays where it is
+            if self.ctrl & CTRL_CRC == 0 {
+                let data = if ready { self.write_data } else { 0 };
+                if let Some(byte) = self.sides[side].get_mut(self.position) {
+                    *byte = data;
+                }
+                self.transfer_complete = true;
+                self.disk_irq |= irq;
+            }
+            self.gap_ended = false;
+        }
+
+        self.position += 1;
+        if self.position >= self.sides[side].len() {
+            self.end_of_head = true;
+            self.scanning = false;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+
+    fn register_load(&self, addr: u16) -> Option<u8> {
+        let inserted = self.disk_inserted();
+        match addr {
+            0x4030 => Some(
+                (self.timer_irq as u8)
+                    | (self.transfer_complete as u8) << 1
+                    | (self.end_of_head as u8) << 6,
+            ),
+            0x4031 => Some(self.read_data),
+            // the upper bits are left to open bus, which is the $40 of the address
+            0x4032 => Some(
+                0x40 | (!inserted as u8) | (!(inserted && self.scanning) as u8) << 1
+                    | (!inserted as u8) << 2,
+            ),
+            // expansion port, bit 7 says the batteries are good
+            0x4033 => Some(0x80),
+            _ => None,
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn power_on(&mut self) {
+        self.disk_registers = false;
+        self.timer_reload = 0;
+        self.timer_counter = 0;
+        self.timer_repeat = false;
+        self.timer_enabled = false;
+        self.timer_irq = false;
+        self.ctrl = 0;
+        self.transfer_complete = false;
+        self.disk_irq = false;
+        self.end_of_head = true;
+        self.scanning = false;
+    }
+
+    /// the reset button only reaches the CPU
+    fn reset(&mut self) {}
+
+    fn prg_load(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4030..=0x4033 => self.register_load(addr),
+            0x6000..=0xdfff => Some(self.prg_ram[addr as usize - 0x6000]),
+            0xe000..=0xffff => Some(self.rom.prg[addr as usize - 0xe000]),
+            _ => None,
+        }
+    }
+
+    fn prg_read(&mut self, addr: u16) -> Option<u8> {
+        let value = self.prg_load(addr);
+        match addr {
+            0x4030 => {
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+            }
+            0x4031 => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+
+        value
+    }
+
+    fn prg_store(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4020 => self.timer_reload = (self.timer_reload & 0xff00) | val as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00ff) | (val as u16) << 8,
+            0x4022 => {
+                self.timer_repeat = val & 0x01 != 0;
+                self.timer_enabled = val & 0x02 != 0 && self.disk_registers;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers = val & 0x01 != 0;
+                if !self.disk_registers {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                }
+            }
+            0x4024..=0x4026 if !self.disk_registers => {}
+            0x4024 => {
+                self.write_data = val;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.ctrl = val;
+                self.disk_irq = false;
+            }
+            0x6000..=0xdfff => self.prg_ram[addr as usize - 0x6000] = val,
+            // expansion port and audio
+            _ => {}
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.ctrl & CTRL_HORIZONTAL != 0 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        }
+    }
+
+    fn chr_load(&mut self, addr: u16) -> u8 {
+        self.chr_ram[addr as usize & 0x1fff]
+    }
+
+    fn chr_store(&mut self, addr: u16, val: u8) {
+        self.chr_ram[addr as usize & 0x1fff] = val;
+    }
+
+    fn next_scanline(&mut self) -> MapperResult {
+        MapperResult::Continue
+    }
+
+    fn tick(&mut self) {
+        self.tick_timer();
+        self.tick_drive();
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.sides.len()
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        self.side
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.side = side.filter(|&side| side < self.sides.len());
+        self.insert_delay = if self.side.is_some() { INSERT_DELAY } else { 0 };
+        self.end_of_head = true;
+        self.scanning = false;
+    }
+}
@@ -1,4 +1,44 @@
-use super::rom::Rom;
+use super::error::{Error, Result};
+use super::fds::Fds;
+use super::rom::{Rom, TRAINER_SIZE};
+
+/// How the four nametables at $2000-$2FFF map onto the console's 2K of CIRAM, or onto extra
+/// VRAM on the cartridge
+///
+/// See: http://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    /// all nametables are the first 1K of CIRAM
+    OneScreenLower,
+    /// all nametables are the second 1K of CIRAM
+    OneScreenUpper,
+    /// $2000 and $2800 share CIRAM, as do $2400 and $2C00 (horizontal arrangement)
+    Vertical,
+    /// $2000 and $2400 share CIRAM, as do $2800 and $2C00 (vertical arrangement)
+    Horizontal,
+    /// the cartridge provides another 2K, so every nametable is distinct
+    FourScreen,
+    /// only found in headers: the mapper switches mirroring at runtime, Mapper::mirroring()
+    /// says how it currently is
+    MapperControlled,
+}
+
+impl Mirroring {
+    /// Returns the offset into nametable memory of a $2000-$2FFF address: 2K of CIRAM, 4K
+    /// with FourScreen
+    pub fn nametable_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize & 0x0fff;
+        let offset = addr & 0x03ff;
+        match *self {
+            Mirroring::OneScreenLower => offset,
+            Mirroring::OneScreenUpper => 0x0400 | offset,
+            Mirroring::Vertical => addr & 0x07ff,
+            // a mapper always resolves MapperControlled, fall back to the header's default
+            Mirroring::Horizontal | Mirroring::MapperControlled => ((addr & 0x0800) >> 1) | offset,
+            Mirroring::FourScreen => addr,
+        }
+    }
+}
 
 #[derive(PartialEq, Eq)]
 pub enum MapperResult {
@@ -7,51 +47,202 @@ pub enum MapperResult {
 }
 
 pub trait Mapper {
-    fn prg_load(&self, addr: u16) -> u8;
+    /// Puts the mapper's registers in their power-on state
+    fn power_on(&mut self);
+    /// Handles the reset button
+    fn reset(&mut self);
+    /// Reads from $4020-$FFFF. None when nothing on the cartridge drives the data bus, so the
+    /// read sees open bus.
+    fn prg_load(&self, addr: u16) -> Option<u8>;
+    /// Reads like prg_load(), for the CPU's reads of registers where reading has side effects
+    /// such as acknowledging an IRQ
+    fn prg_read(&mut self, addr: u16) -> Option<u8> {
+        self.prg_load(addr)
+    }
     fn prg_store(&mut self, addr: u16, val: u8);
+    /// The cartridge's PRG-RAM at $6000-$7FFF, empty if it has none
+    fn prg_ram(&self) -> &[u8];
+    fn prg_ram_mut(&mut self) -> &mut [u8];
+    /// Current nametable mirroring, never MapperControlled
+    fn mirroring(&self) -> Mirroring;
     fn chr_load(&mut self, addr: u16) -> u8;
     fn chr_store(&mut self, addr: u16, val: u8);
     fn next_scanline(&mut self) -> MapperResult;
+    /// Clocks the mapper once per CPU cycle, for mappers with timers
+    fn tick(&mut self) {}
+    /// Called for every CPU bus access, read or write, before it is carried out and whether or
+    /// not it is for the cartridge. As the CPU accesses the bus once per cycle, this counts
+    /// cycles for boards that care about the timing of writes, also without a console around.
+    fn bus_access(&mut self) {}
+    /// Whether the mapper is pulling the IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+    /// Number of disk sides of the Famicom Disk System, cartridges have none
+    fn disk_sides(&self) -> usize {
+        0
+    }
+    /// Side in the disk drive
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+    /// Puts a side in the disk drive, or ejects the disk with None
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+}
+
+pub fn create_mapper(rom: Box<Rom>) -> Result<Box<dyn Mapper>> {
+    match rom.header.mapper() {
+        0 | 1 => check_prg_rom(&rom)?,
+        _ => {}
+    }
+
+    match (rom.header.mapper(), rom.header.submapper()) {
+        (0, _) => Ok(Box::new(Nrom::new(rom)) as Box<dyn Mapper>),
+        // SEROM, SHROM and SH1ROM wire MMC1 up without PRG banking
+        (1, 5) => Ok(Box::new(SxRom::with_fixed_prg(rom)) as Box<dyn Mapper>),
+        (1, _) => Ok(Box::new(SxRom::new(rom)) as Box<dyn Mapper>),
+        (20, _) if rom.disk.is_some() => Ok(Box::new(Fds::new(rom)) as Box<dyn Mapper>),
+        (mapper_id, _) => Err(Error::UnsupportedMapper(mapper_id))
+    }
 }
 
+/// Common name of a mapper number, for the ones most games use
+///
+/// See: http://wiki.nesdev.com/w/index.php/Mapper
+pub fn mapper_name(mapper_id: u16) -> Option<&'static str> {
+    let name = match mapper_id {
+        0 => "NROM",
+        1 => "MMC1",
+        2 => "UxROM",
+        3 => "CNROM",
+        4 => "MMC3",
+        5 => "MMC5",
+        7 => "AxROM",
+        9 => "MMC2",
+        10 => "MMC4",
+        11 => "Color Dreams",
+        13 => "CPROM",
+        16 => "Bandai FCG",
+        19 => "Namco 163",
+        20 => "FDS",
+        21 | 23 | 25 => "VRC2/VRC4",
+        22 => "VRC2",
+        24 | 26 => "VRC6",
+        34 => "BNROM/NINA-001",
+        66 => "GxROM",
+        69 => "Sunsoft FME-7",
+        71 => "Camerica",
+        85 => "VRC7",
+        206 => "Namco 118",
+        _ => return None,
+    };
+
+    Some(name)
+}
 
-pub fn create_mapper(rom: Box<Rom>) -> Box<Mapper> {
-    let mapper_id = rom.header.nes_mapper();
-    println!("mapper id: {}", mapper_id);
-    match mapper_id {
-        0 => Box::new(Nrom { rom }) as Box<Mapper>,
-        1 => Box::new(SxRom::new(rom)) as Box<Mapper>,
-        _ => panic!("unsupported mapper of id {}", mapper_id)
+/// Makes sure PRG-ROM is whole 16K banks, the only sizes NROM and MMC1 can map
+fn check_prg_rom(rom: &Rom) -> Result<()> {
+    let size = rom.prg.len();
+    if size == 0 || !size.is_multiple_of(16384) {
+        Err(Error::InvalidPrgRomSize(size))
+    } else {
+        Ok(())
     }
 }
 
+/// Reads CHR-ROM, or CHR-RAM on boards without any, offsets past the end wrap around
+fn chr_load(chr_rom: &[u8], chr_ram: &[u8], offset: usize) -> u8 {
+    if !chr_rom.is_empty() {
+        chr_rom[offset % chr_rom.len()]
+    } else if !chr_ram.is_empty() {
+        chr_ram[offset % chr_ram.len()]
+    } else {
+        0
+    }
+}
+
+/// Writes CHR-RAM, boards with CHR-ROM ignore the write
+fn chr_store(chr_rom: &[u8], chr_ram: &mut [u8], offset: usize, val: u8) {
+    if chr_rom.is_empty() && !chr_ram.is_empty() {
+        let len = chr_ram.len();
+        chr_ram[offset % len] = val;
+    }
+}
+
+/// Allocates PRG-RAM as sized by the header and copies the ROM's trainer, if any, to
+/// $7000-$71FF. A trainer needs the full 8K, whatever the header says. This is only done once:
+/// a power cycle would otherwise overwrite battery-backed RAM just restored from a save.
+fn new_prg_ram(rom: &Rom) -> Vec<u8> {
+    let mut prg_ram = vec![0; rom.header.prg_ram_bytes()];
+    if let Some(ref trainer) = rom.trainer {
+        if prg_ram.len() < 0x2000 {
+            prg_ram.resize(0x2000, 0);
+        }
+        prg_ram[0x1000..0x1000 + TRAINER_SIZE].copy_from_slice(trainer);
+    }
+
+    prg_ram
+}
+
 /// Mapper 0 (NROM)
 ///
 /// See: http://wiki.nesdev.com/w/index.php/NROM
 pub struct Nrom {
     pub rom: Box<Rom>,
+    /// sized from the header, only a few boards (Family Basic) really have any
+    prg_ram: Vec<u8>,
+    /// sized from the header, used by boards without CHR-ROM
+    chr_ram: Vec<u8>,
+}
+
+impl Nrom {
+    fn new(rom: Box<Rom>) -> Nrom {
+        let prg_ram = new_prg_ram(&rom);
+        let chr_ram = vec![0; rom.header.chr_ram_bytes()];
+        Nrom { rom, prg_ram, chr_ram }
+    }
 }
 
 impl Mapper for Nrom {
-    fn prg_load(&self, addr: u16) -> u8 {
-        if addr < 0x8000 {
-            0u8
+    fn power_on(&mut self) {}
+    fn reset(&mut self) {}
+    fn prg_load(&self, addr: u16) -> Option<u8> {
+        if addr < 0x6000 || (addr < 0x8000 && self.prg_ram.is_empty()) {
+            None
+        } else if addr < 0x8000 {
+            Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()])
         } else if self.rom.prg.len() > 16384 {
-            self.rom.prg[addr as usize & 0x7fff]
+            Some(self.rom.prg[addr as usize & 0x7fff])
         } else {
-            self.rom.prg[addr as usize & 0x3fff]
+            Some(self.rom.prg[addr as usize & 0x3fff])
         }
     }
-    /// can't write to PRG-ROM
+    /// can't write to PRG-ROM, the write is ignored
     fn prg_store(&mut self, addr: u16, val: u8) {
-        panic!("Unsupported");
+        if (0x6000..0x8000).contains(&addr) && !self.prg_ram.is_empty() {
+            let len = self.prg_ram.len();
+            self.prg_ram[(addr as usize - 0x6000) % len] = val;
+        }
+    }
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+    /// NROM's mirroring is soldered on the board
+    fn mirroring(&self) -> Mirroring {
+        match self.rom.header.mirroring() {
+            Mirroring::MapperControlled => Mirroring::Horizontal,
+            mirroring => mirroring,
+        }
     }
     fn chr_load(&mut self, addr: u16) -> u8 {
-        self.rom.chr[addr as usize]
+        chr_load(&self.rom.chr, &self.chr_ram, addr as usize)
     }
-    /// can't write to CHR-ROM
+    /// writes go to CHR-RAM, boards with CHR-ROM ignore them
     fn chr_store(&mut self, addr: u16, val: u8) {
-        panic!("Unsupported");
+        chr_store(&self.rom.chr, &mut self.chr_ram, addr as usize, val);
     }
     fn next_scanline(&mut self) -> MapperResult {
         MapperResult::Continue
@@ -66,12 +257,6 @@ struct SxCtrl {
     val: u8
 }
 
-pub enum Mirroring {
-    OneScreenLower,
-    OneScreenUpper,
-    Vertical,
-    Horizontal,
-}
 
 enum SxPrgBankMode {
     /// Switch 32K at $8000, ignore lower bit
@@ -83,6 +268,15 @@ enum SxPrgBankMode {
 }
 
 impl SxCtrl {
+    fn mirroring(&self) -> Mirroring {
+        match self.val & 3 {
+            0 => Mirroring::OneScreenLower,
+            1 => Mirroring::OneScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
     fn prg_rom_mode(&self) -> SxPrgBankMode {
         match (self.val >> 2) & 3 {
             0 | 1 => SxPrgBankMode::Switch32k,
@@ -91,6 +285,11 @@ impl SxCtrl {
             _ => panic!("can't happen")
         }
     }
+
+    /// Whether CHR is switched as two 4K banks instead of one 8K bank
+    fn chr_4k_banks(&self) -> bool {
+        self.val & 0x10 != 0
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -101,7 +300,7 @@ struct SxRegs {
     chr_bank_0: u8,
     /// $C000-$DFFF
     chr_bank_1: u8,
-    /// #E000-$FFFF
+    /// $E000-$FFFF. Bit 4 disables PRG-RAM.
     prg_bank: u8
 }
 
@@ -112,62 +311,150 @@ pub struct SxRom {
     accum: u8,
     /// write count (at 5 update register)
     write_count: u8,
-    chr_ram: Box<[u8; 8192]>,
+    /// CPU cycles since power on, counted by bus_access
+    cycle: u64,
+    /// cycle of the last write to the serial port
+    last_write: Option<u64>,
+    /// sized from the header, used by boards without CHR-ROM
+    chr_ram: Vec<u8>,
+    prg_ram: Vec<u8>,
+    /// PRG-ROM is a single fixed 32K bank (submapper 5)
+    fixed_prg: bool,
+}
+
+impl SxRegs {
+    fn new() -> SxRegs {
+        SxRegs {
+            ctrl: SxCtrl {
+                val: 3 << 2,
+            },
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0
+        }
+    }
 }
 
 impl SxRom {
     fn new(rom: Box<Rom>) -> SxRom {
+        let prg_ram = new_prg_ram(&rom);
+        let chr_ram = vec![0; rom.header.chr_ram_bytes()];
         SxRom {
             rom,
-            regs: SxRegs {
-                ctrl: SxCtrl {
-                    val: 3 << 2,
-                },
-                chr_bank_0: 0,
-                chr_bank_1: 0,
-                prg_bank: 0
-            },
+            regs: SxRegs::new(),
             accum: 0,
             write_count: 0,
-            chr_ram: Box::new([0; 8192]),
+            cycle: 0,
+            last_write: None,
+            chr_ram,
+            prg_ram,
+            fixed_prg: false,
         }
     }
+
+    fn with_fixed_prg(rom: Box<Rom>) -> SxRom {
+        SxRom {
+            fixed_prg: true,
+            ..SxRom::new(rom)
+        }
+    }
+
+    /// Offset into PRG-RAM for an address in $6000-$7FFF, None while PRG-RAM is disabled
+    fn prg_ram_offset(&self, addr: u16) -> Option<usize> {
+        if self.regs.prg_bank & 0x10 != 0 || self.prg_ram.is_empty() {
+            None
+        } else {
+            Some((addr as usize - 0x6000) % self.prg_ram.len())
+        }
+    }
+
+    /// Offset into CHR memory for a PPU address, after the CHR bank registers
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = addr as usize & 0x1fff;
+        if self.regs.ctrl.chr_4k_banks() {
+            let bank = if addr < 0x1000 { self.regs.chr_bank_0 } else { self.regs.chr_bank_1 };
+            (bank as usize * 0x1000) | (addr & 0x0fff)
+        } else {
+            // 8K mode ignores the lowest bit
+            ((self.regs.chr_bank_0 & 0x1e) as usize * 0x1000) | addr
+        }
+    }
+
+    /// Reads from a 16K PRG-ROM bank, bank numbers past the end of PRG-ROM wrap around
+    fn prg_rom(&self, bank: u8, addr: u16) -> u8 {
+        let bank = bank as usize % (self.rom.prg.len() / 16384);
+        self.rom.prg[(bank * 16384) | ((addr & 0x3fff) as usize)]
+    }
+
+    /// Clears the shift register and fixes the last bank at $C000, as a write with bit 7 set does
+    fn reset_shift_register(&mut self) {
+        self.write_count = 0;
+        self.accum = 0;
+        self.regs.ctrl = SxCtrl {
+            val: self.regs.ctrl.val | (3 << 2)
+        };
+    }
 }
 
 impl Mapper for SxRom {
-    fn prg_load(&self, addr: u16) -> u8 {
-        if addr < 0x8000 {
-            0u8
+    fn power_on(&mut self) {
+        self.regs = SxRegs::new();
+        self.accum = 0;
+        self.write_count = 0;
+        self.last_write = None;
+    }
+
+    fn reset(&mut self) {
+        self.reset_shift_register();
+    }
+
+    fn prg_load(&self, addr: u16) -> Option<u8> {
+        if addr < 0x6000 {
+            None
+        } else if addr < 0x8000 {
+            self.prg_ram_offset(addr).map(|offset| self.prg_ram[offset])
+        } else if self.fixed_prg {
+            // 16K of PRG-ROM is mirrored at $C000
+            Some(self.rom.prg[(addr as usize & 0x7fff) % self.rom.prg.len()])
         } else if addr < 0xc000 {
             let bank = match self.regs.ctrl.prg_rom_mode() {
-                SxPrgBankMode::Switch32k => self.regs.prg_bank & 0xfe,
+                SxPrgBankMode::Switch32k => self.regs.prg_bank & 0x0e,
                 SxPrgBankMode::FixFirstBank => 0,
-                SxPrgBankMode::FixLastBank => self.regs.prg_bank,
+                SxPrgBankMode::FixLastBank => self.regs.prg_bank & 0x0f,
             };
 
-            self.rom.prg[(bank as usize * 16384) | ((addr & 0x3fff) as usize)]
+            Some(self.prg_rom(bank, addr))
         } else {
             let bank = match self.regs.ctrl.prg_rom_mode() {
-                SxPrgBankMode::Switch32k => (self.regs.prg_bank & 0xfe) | 1,
-                SxPrgBankMode::FixFirstBank => self.regs.prg_bank,
-                SxPrgBankMode::FixLastBank => (*self.rom).header.prg_rom_size - 1,
+                SxPrgBankMode::Switch32k => (self.regs.prg_bank & 0x0e) | 1,
+                SxPrgBankMode::FixFirstBank => self.regs.prg_bank & 0x0f,
+                SxPrgBankMode::FixLastBank => (self.rom.prg.len() / 16384 - 1) as u8,
             };
-            self.rom.prg[(bank as usize * 16384) | ((addr & 0x3fff) as usize)]
+            Some(self.prg_rom(bank, addr))
         }
     }
 
     fn prg_store(&mut self, addr: u16, val: u8) {
-        if addr < 0x8000 {
+        if addr < 0x6000 {
+            return;
+        } else if addr < 0x8000 {
+            if let Some(offset) = self.prg_ram_offset(addr) {
+                self.prg_ram[offset] = val;
+            }
+            return;
+        }
+
+        // the serial port ignores a write on the cycle right after another, so the double
+        // write of read-modify-write instructions only counts once
+        let consecutive = self.last_write.is_some_and(|last| self.cycle - last <= 1);
+        self.last_write = Some(self.cycle);
+        if consecutive {
             return;
         }
 
         // check reset
         if (val & 0x80) != 0 {
-            self.write_count = 0;
-            self.accum = 0;
-            self.regs.ctrl = SxCtrl {
-                val: self.regs.ctrl.val | (3 << 2)
-            };
+            self.reset_shift_register();
             return;
         }
 
@@ -195,12 +482,34 @@ impl Mapper for SxRom {
         }
     }
 
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        // boards with extra VRAM wire it up regardless of the control register
+        if self.rom.header.mirroring() == Mirroring::FourScreen {
+            Mirroring::FourScreen
+        } else {
+            self.regs.ctrl.mirroring()
+        }
+    }
+
     fn chr_load(&mut self, addr: u16) -> u8 {
-        self.chr_ram[addr as usize]
+        chr_load(&self.rom.chr, &self.chr_ram, self.chr_offset(addr))
     }
 
     fn chr_store(&mut self, addr: u16, val: u8) {
-        self.chr_ram[addr as usize] = val;
+        let offset = self.chr_offset(addr);
+        chr_store(&self.rom.chr, &mut self.chr_ram, offset, val);
+    }
+
+    fn bus_access(&mut self) {
+        self.cycle += 1;
     }
 
     fn next_scanline(&mut self) -> MapperResult {
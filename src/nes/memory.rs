@@ -1,90 +1,290 @@
 use std::fmt;
+use nes::error::Result;
 use nes::rom::Rom;
 use core::borrow::{Borrow, BorrowMut};
-use nes::mapper::{create_mapper, Mapper};
+use nes::mapper::{create_mapper, Mapper, Mirroring};
+use nes::ppu::Ppu;
+use nes::apu::Apu;
 
-const DEFAULT_MEMORY_SIZE: u32 = 65536; // change to 2048;
+const DEFAULT_MEMORY_SIZE: u32 = 65536;
+/// size of the NES's internal RAM
+const RAM_SIZE: usize = 0x800;
 
+/// A 16-bit address bus the CPU can be attached to. Loads take `&mut self` since reads from
+/// memory-mapped registers can have side effects.
 pub trait Memory {
+    /// Puts the bus and everything on it in its power-on state
+    fn power_on(&mut self);
+    /// Handles the reset button: devices reset their registers, RAM keeps its contents
     fn reset(&mut self);
-    fn load(&self, address: u16) -> u8;
+    fn load(&mut self, address: u16) -> u8;
     fn store(&mut self, address: u16, value: u8);
-    fn is_same_page(address1: u16, address2: u16) -> bool;
+    /// reads a value without any of the side effects a load may have, for debuggers and tracers
+    fn peek(&self, address: u16) -> u8;
+    /// The PPU's scanline and dot, for tracers. Buses without a PPU return None.
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        None
+    }
 }
 
-pub struct NesMemory {
-    memory: [u8; DEFAULT_MEMORY_SIZE as usize],
-    mapper: Box<Mapper>,
+/// returns true if two addresses' higher bits are the same
+/// , aka if they are located in the same page in memory.
+/// i.e. 0x0101 and 0x0103 are on the same page,
+/// but 0x0101 0x0202 are not.
+pub fn is_same_page(address1: u16, address2: u16) -> bool {
+    (address1 ^ address2) >> 8 == 0
 }
 
-impl NesMemory {
-    pub fn new(rom: Box<Rom>) -> Self {
-        let mapper = create_mapper(rom);
-        NesMemory {
-            memory: [0; DEFAULT_MEMORY_SIZE as usize],
-            mapper
+/// Contents of RAM at power on. Real consoles leave it more or less random and a few games
+/// (accidentally) depend on what they find there.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RamPattern {
+    #[default]
+    Zeros,
+    /// every byte $FF
+    Ones,
+    /// pseudo-random bytes, reproducible from the seed
+    Random(u64),
+}
+
+impl RamPattern {
+    pub fn fill(&self, ram: &mut [u8]) {
+        match *self {
+            RamPattern::Zeros => fill(ram, 0x00),
+            RamPattern::Ones => fill(ram, 0xff),
+            RamPattern::Random(seed) => {
+                // xorshift64*, which can't start from 0
+                let mut state = if seed == 0 { 0x2545_f491_4f6c_dd1d } else { seed };
+                for x in ram.iter_mut() {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    *x = (state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8;
+                }
+            }
         }
     }
 }
 
-impl Memory for NesMemory {
-    // set memory at every address to 0
-    fn reset(&mut self) {
-        for x in 0..DEFAULT_MEMORY_SIZE {
-            self.memory[x as usize] = 0;
-        }
+fn fill(memory: &mut [u8], value: u8) {
+    for x in memory.iter_mut() {
+        *x = value;
     }
+}
 
-    // retrieve value from memory at address
-    fn load(&self, address: u16) -> u8 {
-        if address < 0x2000 {
-            self.memory[address as usize]
-        } else if address < 0x4000 {
-            // ppu
-            self.memory[address as usize]
-        } else if address == 0x4016 {
-            // input
-            self.memory[address as usize]
-        } else if address <= 0x4018 {
-            // apu
-            self.memory[address as usize]
-        } else if address < 0x6000 {
-            // some mappers?
-            self.memory[address as usize]
-        } else {
-            let mapper: &Mapper = self.mapper.borrow();
-            mapper.prg_load(address)
+/// A plain 64K of RAM with nothing mapped into it, for running the CPU on its own
+pub struct FlatMemory {
+    memory: Box<[u8; DEFAULT_MEMORY_SIZE as usize]>,
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        FlatMemory {
+            memory: Box::new([0; DEFAULT_MEMORY_SIZE as usize]),
         }
     }
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        FlatMemory::new()
+    }
+}
+
+impl Memory for FlatMemory {
+    fn power_on(&mut self) {
+        fill(&mut self.memory[..], 0);
+    }
+
+    fn reset(&mut self) {}
+
+    fn load(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
 
-    // stores value in the given address and returns the previous value
     fn store(&mut self, address: u16, value: u8) {
-        if address < 0x2000 {
-            self.memory[address as usize] = value;
-        } else if address < 0x4000 {
-            // ppu
+        self.memory[address as usize] = value;
+    }
 
-        } else if address == 0x4016 {
-            // input
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+}
 
-        } else if address <= 0x4018 {
-            // apu
+impl fmt::Debug for FlatMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "FlatMemory")
+    }
+}
+
+pub struct NesMemory {
+    /// 2K of internal RAM, mirrored through $0000-$1FFF
+    ram: [u8; RAM_SIZE],
+    mapper: Box<dyn Mapper>,
+    /// whether the cartridge's PRG-RAM is battery backed
+    battery: bool,
+    /// what RAM is filled with by power_on()
+    pub ram_pattern: RamPattern,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    /// last value driven on the data bus
+    open_bus: u8,
+}
 
-        } else if address < 0x6000 {
-            // some mappers?
+/// Part of the CPU address space an address decodes to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Region {
+    /// $0000-$1FFF, with the offset into RAM
+    Ram(usize),
+    /// $2000-$3FFF, with the PPU register number
+    Ppu(u16),
+    /// $4000-$401F: APU, OAM DMA and controller ports
+    Io,
+    /// $4020-$FFFF
+    Cartridge,
+}
+
+/// CPU memory map
+///
+/// See: http://wiki.nesdev.com/w/index.php/CPU_memory_map
+fn decode(address: u16) -> Region {
+    match address {
+        0x0000..=0x1fff => Region::Ram(address as usize % RAM_SIZE),
+        0x2000..=0x3fff => Region::Ppu(address & 7),
+        0x4000..=0x401f => Region::Io,
+        _ => Region::Cartridge,
+    }
+}
 
+impl NesMemory {
+    pub fn new(rom: Box<Rom>) -> Result<Self> {
+        let battery = rom.header.battery();
+        let mapper = create_mapper(rom)?;
+        Ok(NesMemory {
+            ram: [0; RAM_SIZE],
+            mapper,
+            battery,
+            ram_pattern: RamPattern::default(),
+            ppu: Ppu::new(),
+            apu: Apu::new(),
+            open_bus: 0,
+        })
+    }
+
+    /// Value of the data bus, which keeps the last value driven on it: reads from addresses
+    /// nothing responds to, and the bits a device leaves undriven, return this
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
+    pub fn mapper(&self) -> &dyn Mapper {
+        self.mapper.borrow()
+    }
+
+    pub fn mapper_mut(&mut self) -> &mut dyn Mapper {
+        self.mapper.borrow_mut()
+    }
+
+    /// Current nametable mirroring, as the cartridge wires it
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    /// The cartridge's PRG-RAM if it is battery backed, i.e. what belongs in a save file
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        if self.battery && !self.mapper.prg_ram().is_empty() {
+            Some(self.mapper.prg_ram())
+        } else {
+            None
+        }
+    }
+
+    pub fn battery_ram_mut(&mut self) -> Option<&mut [u8]> {
+        if self.battery && !self.mapper.prg_ram().is_empty() {
+            Some(self.mapper.prg_ram_mut())
         } else {
-            let mut mapper: &mut Mapper = self.mapper.borrow_mut();
-            mapper.prg_store(address, value);
+            None
+        }
+    }
+}
+
+impl Memory for NesMemory {
+    fn power_on(&mut self) {
+        self.ram_pattern.fill(&mut self.ram);
+        self.ppu.power_on();
+        self.apu.power_on();
+        self.mapper.power_on();
+    }
+
+    fn reset(&mut self) {
+        self.ppu.reset();
+        self.apu.reset();
+        self.mapper.reset();
+    }
+
+    fn load(&mut self, address: u16) -> u8 {
+        self.mapper.bus_access();
+        let value = match decode(address) {
+            Region::Ppu(register) => self.ppu.load(register),
+            Region::Cartridge => {
+                let mapper: &mut dyn Mapper = self.mapper.borrow_mut();
+                mapper.prg_read(address).unwrap_or(self.open_bus)
+            }
+            Region::Io if address == 0x4015 => {
+                // $4015 is read inside the CPU, the external data bus isn't driven
+                let status = self.apu.load(address);
+                return (status & !0x20) | (self.open_bus & 0x20);
+            }
+            _ => self.peek(address),
+        };
+
+        self.open_bus = value;
+        value
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        match decode(address) {
+            Region::Ram(offset) => self.ram[offset],
+            Region::Ppu(register) => self.ppu.peek(register),
+            Region::Io => match address {
+                0x4015 => (self.apu.peek(address) & !0x20) | (self.open_bus & 0x20),
+                // the controller ports only drive the low 5 bits, and no controllers are
+                // plugged in yet
+                0x4016 | 0x4017 => self.open_bus & 0xe0,
+                _ => self.open_bus,
+            },
+            Region::Cartridge => {
+                let mapper: &dyn Mapper = self.mapper.borrow();
+                mapper.prg_load(address).unwrap_or(self.open_bus)
+            }
+        }
+    }
+
+    fn store(&mut self, address: u16, value: u8) {
+        self.mapper.bus_access();
+        self.open_bus = value;
+
+        match decode(address) {
+            Region::Ram(offset) => self.ram[offset] = value,
+            Region::Ppu(register) => self.ppu.store(register, value),
+            Region::Io => match address {
+                // OAM DMA isn't implemented yet
+                0x4014 => {}
+                // controller strobe
+                0x4016 => {}
+                0x4000..=0x4017 => self.apu.store(address, value),
+                // APU test registers, disabled on retail consoles
+                _ => {}
+            },
+            Region::Cartridge => {
+                let mapper: &mut dyn Mapper = self.mapper.borrow_mut();
+                mapper.prg_store(address, value);
+            }
         }
     }
 
-    /// returns true if two addresses' higher bits are the same
-    /// , aka if they are located in the same page in memory.
-    /// i.e. 0x0101 and 0x0103 are on the same page,
-    /// but 0x0101 0x0202 are not.
-    fn is_same_page(address1: u16, address2: u16) -> bool {
-        (address1 ^ address2) >> 8 == 0
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        Some((self.ppu.scanline, self.ppu.dot))
     }
 }
 
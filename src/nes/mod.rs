@@ -1,4 +1,13 @@
 pub mod cpu;
 pub mod memory;
 pub mod rom;
+pub mod unif;
+pub mod fds;
 pub mod mapper;
+pub mod trace;
+pub mod disasm;
+pub mod asm;
+pub mod error;
+pub mod console;
+pub mod ppu;
+pub mod apu;
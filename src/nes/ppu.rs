@@ -0,0 +1,136 @@
+/// dots (PPU cycles) per scanline
+pub const DOTS_PER_SCANLINE: u16 = 341;
+/// scanlines per NTSC frame, including vblank and the pre-render line
+pub const SCANLINES_PER_FRAME: u16 = 262;
+/// first scanline of vblank
+pub const VBLANK_SCANLINE: u16 = 241;
+pub const PRE_RENDER_SCANLINE: u16 = 261;
+
+/// PPUCTRL: generate an NMI at the start of vblank
+const CTRL_NMI: u8 = 1 << 7;
+/// PPUMASK: show background / sprites
+const MASK_RENDERING: u8 = 0x18;
+/// PPUSTATUS: sprite overflow, sprite 0 hit and vblank
+const STATUS_OVERFLOW: u8 = 1 << 5;
+const STATUS_SPRITE_0: u8 = 1 << 6;
+const STATUS_VBLANK: u8 = 1 << 7;
+
+/// Picture processing unit. So far only the frame timing is emulated, along with the registers
+/// that take part in it (the vblank flag and NMI); nothing is rendered.
+///
+/// See: http://wiki.nesdev.com/w/index.php/PPU_frame_timing
+#[derive(Debug)]
+pub struct Ppu {
+    /// 0-261
+    pub scanline: u16,
+    /// 0-340
+    pub dot: u16,
+    /// number of vblanks entered since power on
+    pub frame: u64,
+    /// $2000
+    ctrl: u8,
+    /// $2001
+    mask: u8,
+    /// $2002
+    status: u8,
+    /// odd frames are a dot shorter while rendering
+    odd_frame: bool,
+    /// the PPU's own data bus latch, holding the last value written to any register. Reads of
+    /// write-only registers and of the unused bits of PPUSTATUS return it.
+    latch: u8,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            odd_frame: false,
+            latch: 0,
+        }
+    }
+
+    pub fn power_on(&mut self) {
+        *self = Ppu::new();
+    }
+
+    /// The reset button clears PPUCTRL and PPUMASK but leaves the frame timing running
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+    }
+
+    /// Advances by a single dot
+    pub fn tick(&mut self) {
+        let skip = self.odd_frame && self.rendering() && self.scanline == PRE_RENDER_SCANLINE &&
+            self.dot == DOTS_PER_SCANLINE - 2;
+
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE || skip {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+
+        if self.dot == 1 {
+            if self.scanline == VBLANK_SCANLINE {
+                self.status |= STATUS_VBLANK;
+                self.frame += 1;
+            } else if self.scanline == PRE_RENDER_SCANLINE {
+                self.status &= !(STATUS_VBLANK | STATUS_SPRITE_0 | STATUS_OVERFLOW);
+            }
+        }
+    }
+
+    /// Level of the PPU's /NMI output, asserted while in vblank with NMIs enabled
+    pub fn nmi(&self) -> bool {
+        self.status & STATUS_VBLANK != 0 && self.ctrl & CTRL_NMI != 0
+    }
+
+    pub fn in_vblank(&self) -> bool {
+        self.status & STATUS_VBLANK != 0
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask & MASK_RENDERING != 0
+    }
+
+    /// Reads one of the 8 registers mirrored through $2000-$3FFF
+    pub fn load(&mut self, address: u16) -> u8 {
+        let value = self.peek(address);
+        if address & 7 == 2 {
+            // reading the status acknowledges vblank
+            self.status &= !STATUS_VBLANK;
+        }
+        value
+    }
+
+    pub fn peek(&self, address: u16) -> u8 {
+        match address & 7 {
+            2 => (self.status & 0xe0) | (self.latch & 0x1f),
+            _ => self.latch,
+        }
+    }
+
+    pub fn store(&mut self, address: u16, value: u8) {
+        self.latch = value;
+        match address & 7 {
+            0 => self.ctrl = value,
+            1 => self.mask = value,
+            _ => {}
+        }
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new()
+    }
+}
@@ -1,20 +1,39 @@
-use std::io::Read;
+use super::error::{Error, Result};
+use super::mapper::{mapper_name, Mirroring};
+use super::fds::{self, Disk};
+use super::unif;
+use std::io::{ErrorKind, Read};
 use std::fmt;
 
+/// Size of the trainer some dumps carry between the header and PRG-ROM
+pub const TRAINER_SIZE: usize = 512;
+
 #[derive(Debug)]
 pub struct Rom {
     pub header: NesHeader,
+    /// UNIF board name, for images the header was made up for
+    pub board: Option<String>,
+    /// Trainer, loaded to $7000-$71FF by the copier the dump was made with
+    pub trainer: Option<Vec<u8>>,
     /// PRG-ROM
     pub prg: Vec<u8>,
     /// CHR-ROM
     pub chr: Vec<u8>,
+    /// Famicom Disk System disk, the BIOS is then in PRG-ROM
+    pub disk: Option<Disk>,
 }
 
 impl Rom {
-    pub fn load(r: &mut Read) -> Rom {
+    /// Loads an iNES, NES 2.0 or UNIF image
+    pub fn load(r: &mut dyn Read) -> Result<Rom> {
         let mut header = [0u8; 16];
-        let mut total = 0;
-        read_to_buf(&mut header, r);
+        read_to_buf(&mut header, r)?;
+
+        if header[..4] == unif::MAGIC[..] {
+            return unif::load(&header, r);
+        } else if fds::is_disk_image(&header) {
+            return Err(Error::MissingBios);
+        }
 
         let header = NesHeader {
             magic: [
@@ -27,51 +46,158 @@ impl Rom {
             chr_rom_size: header[5],
             flags_6: header[6],
             flags_7: header[7],
-            prg_ram_size: header[8],
+            flags_8: header[8],
             flags_9: header[9],
             flags_10: header[10],
-            zero: [0; 5],
+            flags_11: header[11],
+            flags_12: header[12],
+            flags_13: header[13],
+            flags_14: header[14],
+            flags_15: header[15],
         };
 
         if header.magic != *b"NES\x1a" {
-            panic!("Invalid header magic");
+            return Err(Error::InvalidHeader);
         }
 
-        let prg_bytes = header.prg_rom_size as usize * 16384;
-        let mut prg_rom = vec![0u8; prg_bytes];
-        read_to_buf(&mut prg_rom, r);
+        let trainer = if header.trainer() {
+            let mut trainer = vec![0u8; TRAINER_SIZE];
+            read_to_buf(&mut trainer, r)?;
+            Some(trainer)
+        } else {
+            None
+        };
 
-        let chr_bytes = header.chr_rom_size as usize * 8192;
-        let mut chr_rom = vec![0u8; chr_bytes];
-        read_to_buf(&mut chr_rom, r);
+        if header.prg_rom_bytes() == 0 {
+            return Err(Error::InvalidPrgRomSize(0));
+        }
+        let prg_rom = read_to_vec(header.prg_rom_bytes(), r)?;
+        let chr_rom = read_to_vec(header.chr_rom_bytes(), r)?;
 
-        Rom {
+        Ok(Rom {
             header,
+            board: None,
+            trainer,
             prg: prg_rom,
             chr: chr_rom,
-        }
+            disk: None,
+        })
+    }
+
+    /// Loads a Famicom Disk System disk image, with or without the fwNES header, along with
+    /// the disksys.rom BIOS
+    pub fn load_fds(disk: &mut dyn Read, bios: &mut dyn Read) -> Result<Rom> {
+        fds::load(disk, bios)
     }
 }
 
-fn read_to_buf(mut buf: &mut [u8], rd: &mut Read) {
+pub(crate) fn read_to_buf(buf: &mut [u8], rd: &mut dyn Read) -> Result<()> {
     let mut total = 0;
     while total < buf.len() {
-        let count = rd.read(&mut buf[total..]).unwrap_or(0);
+        let count = match rd.read(&mut buf[total..]) {
+            Ok(count) => count,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::Io(err)),
+        };
         if count == 0 {
-            panic!("EOF reached prematurely");
+            return Err(Error::Truncated);
         }
 
         total += count;
     }
+
+    Ok(())
+}
+
+/// Reads exactly len bytes. Unlike read_to_buf, memory is only allocated as data arrives, so a
+/// bogus size in a header can't exhaust it.
+pub(crate) fn read_to_vec(len: usize, rd: &mut dyn Read) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    rd.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(Error::Truncated);
+    }
+
+    Ok(buf)
+}
+
+/// Header format, told apart by bits 2-3 of flags_7
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeaderFormat {
+    /// archaic iNES or iNES 0.7, where bytes 7-15 may hold garbage such as "DiskDude!" and
+    /// only flags_6 can be trusted
+    Archaic,
+    INes,
+    Nes20,
+}
+
+/// Which console the cartridge is for
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConsoleType {
+    /// NES or Famicom
+    Nes,
+    VsSystem,
+    PlayChoice10,
+    /// NES 2.0 extended console type, see flags_13
+    Extended(u8),
+}
+
+/// CPU/PPU timing the cartridge expects
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// RP2C02, North America and Japan
+    Ntsc,
+    /// RP2C07, Europe and Australia
+    Pal,
+    /// runs on either
+    MultiRegion,
+    /// UA6538
+    Dendy,
 }
 
+/// Whether a mapper switches nametable mirroring itself, so bit 0 of flags_6 means nothing
+fn mapper_controls_mirroring(mapper_id: u16) -> bool {
+    match mapper_id {
+        // MMC1, MMC3, MMC5, AxROM, MMC2, MMC4, Bandai FCG, Namco 163, FDS, VRC2/4/6, FME-7,
+        // VRC7
+        1 | 4 | 5 | 7 | 9 | 10 | 16 | 19 | 20 | 21..=26 | 69 | 85 => true,
+        _ => false,
+    }
+}
+
+/// Size in bytes of a NES 2.0 RAM/NVRAM shift count: 64 << shift, or nothing at all for 0
+fn shift_bytes(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+/// Size in bytes of a NES 2.0 ROM size, given its LSB byte, MSB nibble and the iNES unit size
+fn rom_bytes(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0f {
+        // EEEEEEMM: 2^E * (MM * 2 + 1)
+        let multiplier = (lsb as usize & 0x03) * 2 + 1;
+        1usize
+            .checked_shl(lsb as u32 >> 2)
+            .and_then(|bytes| bytes.checked_mul(multiplier))
+            .unwrap_or(usize::MAX)
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * unit
+    }
+}
+
+/// iNES header, with the NES 2.0 extensions
+///
+/// See: http://wiki.nesdev.com/w/index.php/INES and http://wiki.nesdev.com/w/index.php/NES_2.0
 #[derive(Debug)]
 pub struct NesHeader {
     /// 'N' 'E' 'S' '\x1a'
     pub magic: [u8; 4],
-    /// number of 16k units of PRG-ROM
+    /// number of 16k units of PRG-ROM, the LSB of it in NES 2.0
     pub prg_rom_size: u8,
-    /// number of 8k units of CHR-ROM
+    /// number of 8k units of CHR-ROM, the LSB of it in NES 2.0
     pub chr_rom_size: u8,
     /// MMMMATPA
     ///
@@ -82,47 +208,259 @@ pub struct NesHeader {
     /// T: ROM contains a trainer
     /// P: Cartridge has persistent memory
     pub flags_6: u8,
-    /// MMMMVVPU
+    /// MMMMVVCC
     ///
     /// M: High nibble of mapper number
     /// V: If 0b10, all following flags are NES 2.0 format
-    /// P: Rom is for PlayChoice-10
-    /// U: Rom is for Unisystem
+    /// C: Console type: 0 NES/Famicom, 1 Vs. System, 2 PlayChoice-10, 3 extended (NES 2.0).
+    ///    iNES only has the two low bits as flags.
     pub flags_7: u8,
-    // number of 8k units of PRG-RAM
-    pub prg_ram_size:u8,
-    /// RRRRRRRT
+    /// iNES: number of 8k units of PRG-RAM
     ///
+    /// NES 2.0: SSSSMMMM
+    /// S: Submapper number
+    /// M: Bits 8-11 of mapper number
+    pub flags_8: u8,
+    /// iNES: RRRRRRRT
     /// R: Reserved (= 0)
     /// T: 0 for NTSC, 1 for PAL
+    ///
+    /// NES 2.0: CCCCPPPP
+    /// C: CHR-ROM size MSB
+    /// P: PRG-ROM size MSB
     pub flags_9: u8,
+    /// iNES: unofficial, mostly unused
+    ///
+    /// NES 2.0: NNNNVVVV
+    /// N: PRG-NVRAM shift count
+    /// V: PRG-RAM shift count
     pub flags_10: u8,
-    /// always zero
-    pub zero: [u8; 5],
+    /// NES 2.0: NNNNVVVV
+    ///
+    /// N: CHR-NVRAM shift count
+    /// V: CHR-RAM shift count
+    pub flags_11: u8,
+    /// NES 2.0: ......TT
+    ///
+    /// T: CPU/PPU timing: 0 NTSC, 1 PAL, 2 multiple-region, 3 Dendy
+    pub flags_12: u8,
+    /// NES 2.0: HHHHPPPP for the Vs. System
+    ///
+    /// H: Vs. hardware type
+    /// P: Vs. PPU type
+    ///
+    /// ....CCCC for extended consoles
+    /// C: Extended console type
+    pub flags_13: u8,
+    /// NES 2.0: ......RR
+    ///
+    /// R: Number of miscellaneous ROMs
+    pub flags_14: u8,
+    /// NES 2.0: ..DDDDDD
+    ///
+    /// D: Default expansion device
+    pub flags_15: u8,
 }
 
 impl NesHeader {
-    /// Returns the mapper ID
-    pub fn mapper(&self) -> u8 {
-        (self.flags_7 & 0xf0) | (self.flags_6 >> 4)
+    pub fn format(&self) -> HeaderFormat {
+        let padding = [self.flags_12, self.flags_13, self.flags_14, self.flags_15];
+        match self.flags_7 & 0x0c {
+            0x08 => HeaderFormat::Nes20,
+            0x00 if padding == [0; 4] => HeaderFormat::INes,
+            _ => HeaderFormat::Archaic,
+        }
+    }
+    fn is_nes20(&self) -> bool {
+        self.format() == HeaderFormat::Nes20
+    }
+    fn is_archaic(&self) -> bool {
+        self.format() == HeaderFormat::Archaic
+    }
+    /// Returns the mapper ID, 12 bits in NES 2.0 and only the lower nibble for archaic headers
+    pub fn mapper(&self) -> u16 {
+        let mapper = ((self.flags_7 & 0xf0) | (self.flags_6 >> 4)) as u16;
+        match self.format() {
+            HeaderFormat::Archaic => self.nes_mapper() as u16,
+            HeaderFormat::INes => mapper,
+            HeaderFormat::Nes20 => ((self.flags_8 as u16 & 0x0f) << 8) | mapper,
+        }
+    }
+    /// Returns the common name of the mapper, if it is a well known one
+    pub fn mapper_name(&self) -> Option<&'static str> {
+        mapper_name(self.mapper())
     }
     /// Returns the lower nibble of the mapper ID
     pub fn nes_mapper(&self) -> u8 {
         self.flags_6 >> 4
     }
+    /// Returns the NES 2.0 submapper, 0 for iNES
+    pub fn submapper(&self) -> u8 {
+        if self.is_nes20() {
+            self.flags_8 >> 4
+        } else {
+            0
+        }
+    }
+    /// Returns the nametable mirroring: the four-screen bit, what the solder pads in bit 0
+    /// select, or MapperControlled for mappers that switch it themselves
+    pub fn mirroring(&self) -> Mirroring {
+        if self.flags_6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if mapper_controls_mirroring(self.mapper()) {
+            Mirroring::MapperControlled
+        } else if self.flags_6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        }
+    }
     pub fn trainer(&self) -> bool {
         (self.flags_6 & 0x04) != 0
     }
+    /// Whether the cartridge's PRG-RAM is battery backed
+    pub fn battery(&self) -> bool {
+        (self.flags_6 & 0x02) != 0
+    }
+    /// Returns the size of PRG-ROM in bytes
+    pub fn prg_rom_bytes(&self) -> usize {
+        if self.is_nes20() {
+            rom_bytes(self.prg_rom_size, self.flags_9 & 0x0f, 16384)
+        } else {
+            self.prg_rom_size as usize * 16384
+        }
+    }
+    /// Returns the size of CHR-ROM in bytes
+    pub fn chr_rom_bytes(&self) -> usize {
+        if self.is_nes20() {
+            rom_bytes(self.chr_rom_size, self.flags_9 >> 4, 8192)
+        } else {
+            self.chr_rom_size as usize * 8192
+        }
+    }
+    /// Returns the size of PRG-RAM in bytes, battery backed or not. Older iNES dumps leave
+    /// flags_8 at 0, which means 8K for compatibility.
+    pub fn prg_ram_bytes(&self) -> usize {
+        if self.is_nes20() {
+            shift_bytes(self.flags_10 & 0x0f) + self.prg_nvram_bytes()
+        } else if self.flags_8 == 0 || self.is_archaic() {
+            8192
+        } else {
+            self.flags_8 as usize * 8192
+        }
+    }
+    /// Returns the size of battery-backed PRG-RAM in bytes. iNES can't tell it apart from the
+    /// rest of PRG-RAM.
+    pub fn prg_nvram_bytes(&self) -> usize {
+        if self.is_nes20() {
+            shift_bytes(self.flags_10 >> 4)
+        } else if self.battery() {
+            self.prg_ram_bytes()
+        } else {
+            0
+        }
+    }
+    /// Returns the size of CHR-RAM in bytes, battery backed or not. iNES carts without
+    /// CHR-ROM have 8K.
+    pub fn chr_ram_bytes(&self) -> usize {
+        if self.is_nes20() {
+            shift_bytes(self.flags_11 & 0x0f) + self.chr_nvram_bytes()
+        } else if self.chr_rom_size == 0 {
+            8192
+        } else {
+            0
+        }
+    }
+    /// Returns the size of battery-backed CHR-RAM in bytes, only NES 2.0 has any
+    pub fn chr_nvram_bytes(&self) -> usize {
+        if self.is_nes20() {
+            shift_bytes(self.flags_11 >> 4)
+        } else {
+            0
+        }
+    }
+    pub fn console_type(&self) -> ConsoleType {
+        match self.flags_7 & 0x03 {
+            _ if self.is_archaic() => ConsoleType::Nes,
+            0 => ConsoleType::Nes,
+            1 => ConsoleType::VsSystem,
+            2 => ConsoleType::PlayChoice10,
+            // both flags set means nothing in iNES
+            _ if !self.is_nes20() => ConsoleType::Nes,
+            _ => ConsoleType::Extended(self.flags_13 & 0x0f),
+        }
+    }
+    /// Returns the Vs. System PPU type, 0 (RP2C03B) for iNES
+    pub fn vs_ppu_type(&self) -> u8 {
+        if self.is_nes20() {
+            self.flags_13 & 0x0f
+        } else {
+            0
+        }
+    }
+    /// Returns the Vs. System hardware type, 0 (Vs. Unisystem) for iNES
+    pub fn vs_hardware_type(&self) -> u8 {
+        if self.is_nes20() {
+            self.flags_13 >> 4
+        } else {
+            0
+        }
+    }
+    pub fn timing(&self) -> Timing {
+        if self.is_archaic() {
+            return Timing::Ntsc;
+        } else if !self.is_nes20() {
+            return if self.flags_9 & 0x01 != 0 {
+                Timing::Pal
+            } else {
+                Timing::Ntsc
+            };
+        }
+
+        match self.flags_12 & 0x03 {
+            0 => Timing::Ntsc,
+            1 => Timing::Pal,
+            2 => Timing::MultiRegion,
+            _ => Timing::Dendy,
+        }
+    }
+    /// Returns the number of miscellaneous ROMs following CHR-ROM, 0 for iNES
+    pub fn misc_roms(&self) -> u8 {
+        if self.is_nes20() {
+            self.flags_14 & 0x03
+        } else {
+            0
+        }
+    }
+    /// Returns the default expansion device, 0 (unspecified) for iNES
+    ///
+    /// See: http://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+    pub fn expansion_device(&self) -> u8 {
+        if self.is_nes20() {
+            self.flags_15 & 0x3f
+        } else {
+            0
+        }
+    }
 }
 
 impl fmt::Display for NesHeader {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "PRG-ROM: {} KB, CHR-ROM: {} KB, Mapper: {} ({}), Trainer: {}",
-            self.prg_rom_size as u32 * 16,
-            self.chr_rom_size as u32 * 8,
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}, PRG-ROM: {} KB, CHR-ROM: {} KB, Mapper: {}",
+            self.format(),
+            self.prg_rom_bytes() / 1024,
+            self.chr_rom_bytes() / 1024,
             self.mapper(),
-            self.nes_mapper(),
+        )?;
+        if let Some(name) = self.mapper_name() {
+            write!(f, " ({})", name)?;
+        }
+        write!(f, ", Submapper: {}, Mirroring: {:?}, Trainer: {}, Battery: {}, Timing: {:?}",
+            self.submapper(),
+            self.mirroring(),
             self.trainer(),
+            self.battery(),
+            self.timing(),
         )
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,27 @@
+use std::fmt;
+use std::io::Write;
+
+/// Destination for the nestest.log style trace the CPU writes when tracing is enabled. See
+/// Cpu::trace_line() for the format. The PPU column is only the PPU's real position on a bus
+/// that reports it, Memory::ppu_position(); elsewhere it is worked out from the CPU cycle.
+pub struct Tracer {
+    out: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(out: W) -> Self {
+        Tracer { out: Box::new(out) }
+    }
+
+    /// Writes a single line to the trace. A trace that can't be written is not worth stopping
+    /// emulation for, so errors are ignored.
+    pub fn write_line(&mut self, line: &str) {
+        let _ = writeln!(self.out, "{}", line);
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tracer")
+    }
+}
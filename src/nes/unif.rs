@@ -0,0 +1,194 @@
+use super::error::{Error, Result};
+use super::rom::{read_to_buf, read_to_vec, NesHeader, Rom};
+use std::io::Read;
+
+/// UNIF files start with this, followed by a 32-bit revision and 24 reserved bytes
+pub const MAGIC: &[u8; 4] = b"UNIF";
+const HEADER_SIZE: usize = 32;
+
+/// Loads a UNIF image, given the first 16 bytes of it Rom::load already read
+///
+/// UNIF names the board instead of numbering the mapper, so a NES 2.0 header is made up from
+/// the board and the MIRR, BATR and TVCI chunks for the rest of the emulator to go by.
+///
+/// See: http://wiki.nesdev.com/w/index.php/UNIF
+pub fn load(start: &[u8; 16], r: &mut dyn Read) -> Result<Rom> {
+    if start[..4] != MAGIC[..] {
+        return Err(Error::InvalidHeader);
+    }
+    let mut reserved = [0u8; HEADER_SIZE - 16];
+    read_to_buf(&mut reserved, r)?;
+
+    let mut board = None;
+    let mut prg_chunks: [Vec<u8>; 16] = Default::default();
+    let mut chr_chunks: [Vec<u8>; 16] = Default::default();
+    let mut mirroring = None;
+    let mut battery = false;
+    // NES 2.0 numbers NTSC, PAL and both the way TVCI does
+    let mut timing = 0;
+
+    while let Some((id, data)) = read_chunk(r)? {
+        match &id {
+            b"MAPR" => {
+                let name = data.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                board = Some(String::from_utf8_lossy(name).trim().to_string());
+            }
+            b"MIRR" => mirroring = data.first().cloned(),
+            b"BATR" => battery = data.first() != Some(&0),
+            b"TVCI" => timing = data.first().cloned().filter(|&tv| tv <= 2).unwrap_or(0),
+            _ => {
+                // PRG0-PRGF and CHR0-CHRF hold ROM, anything else (NAME, READ, DINF, CTRL,
+                // PCKn/CCKn checksums...) is only informational
+                if let Some(bank) = (id[3] as char).to_digit(16) {
+                    match &id[..3] {
+                        b"PRG" => prg_chunks[bank as usize] = data,
+                        b"CHR" => chr_chunks[bank as usize] = data,
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let board = board.ok_or(Error::InvalidHeader)?;
+    let (mapper, submapper) = match board_mapper(&board) {
+        Some(mapper) => mapper,
+        None => return Err(Error::UnsupportedBoard(board)),
+    };
+    let prg = prg_chunks.concat();
+    let chr = chr_chunks.concat();
+    if prg.is_empty() {
+        return Err(Error::InvalidHeader);
+    }
+
+    let (prg_lsb, prg_msb) = rom_size(prg.len(), 16384);
+    let (chr_lsb, chr_msb) = rom_size(chr.len(), 8192);
+    // 8K of PRG-RAM, battery backed or not, and 8K of CHR-RAM without CHR-ROM
+    let prg_ram_shift = 7;
+    let chr_ram_shift = if chr.is_empty() { 7 } else { 0 };
+    // the one-screen settings have no place in the header, the board's mapper handles them
+    let arrangement = match mirroring {
+        Some(1) => 0x01,
+        Some(4) => 0x08,
+        _ => 0x00,
+    };
+
+    let header = NesHeader {
+        magic: *b"NES\x1a",
+        prg_rom_size: prg_lsb,
+        chr_rom_size: chr_lsb,
+        flags_6: ((mapper as u8 & 0x0f) << 4) | arrangement | if battery { 0x02 } else { 0 },
+        flags_7: (mapper as u8 & 0xf0) | 0x08,
+        flags_8: (submapper << 4) | (mapper >> 8) as u8,
+        flags_9: (chr_msb << 4) | prg_msb,
+        flags_10: if battery { prg_ram_shift << 4 } else { prg_ram_shift },
+        flags_11: chr_ram_shift,
+        flags_12: timing,
+        flags_13: 0,
+        flags_14: 0,
+        flags_15: 0,
+    };
+
+    Ok(Rom {
+        header,
+        board: Some(board),
+        trainer: None,
+        prg,
+        chr,
+        disk: None,
+    })
+}
+
+/// Reads the next chunk's ID and data, or None at the end of the file
+fn read_chunk(r: &mut dyn Read) -> Result<Option<([u8; 4], Vec<u8>)>> {
+    let mut chunk_header = Vec::new();
+    r.take(8).read_to_end(&mut chunk_header)?;
+    match chunk_header.len() {
+        0 => return Ok(None),
+        8 => {}
+        _ => return Err(Error::Truncated),
+    }
+
+    let id = [chunk_header[0], chunk_header[1], chunk_header[2], chunk_header[3]];
+    let len = chunk_header[4] as usize
+        | (chunk_header[5] as usize) << 8
+        | (chunk_header[6] as usize) << 16
+        | (chunk_header[7] as usize) << 24;
+
+    Ok(Some((id, read_to_vec(len, r)?)))
+}
+
+/// Encodes a ROM size as NES 2.0 LSB and MSB nibble: a number of units, or 2^E * (MM * 2 + 1)
+/// bytes for sizes that aren't a multiple of the unit
+fn rom_size(bytes: usize, unit: usize) -> (u8, u8) {
+    if bytes.is_multiple_of(unit) && bytes / unit < 0xf00 {
+        let units = bytes / unit;
+        return ((units & 0xff) as u8, (units >> 8) as u8);
+    }
+
+    for multiplier in 0..4 {
+        let odd = multiplier * 2 + 1;
+        if bytes.is_multiple_of(odd) && (bytes / odd).is_power_of_two() {
+            let exponent = (bytes / odd).trailing_zeros() as u8;
+            return ((exponent << 2) | multiplier as u8, 0x0f);
+        }
+    }
+
+    // doesn't fit either form, round up to whole units
+    let units = bytes.div_ceil(unit);
+    ((units & 0xff) as u8, (units >> 8) as u8 & 0x0f)
+}
+
+/// Mapper and submapper numbers of a UNIF board name
+///
+/// See: http://wiki.nesdev.com/w/index.php/UNIF_to_NES_2.0_Mapping
+pub fn board_mapper(board: &str) -> Option<(u16, u8)> {
+    // the prefix only says who made the board
+    let name = ["NES-", "HVC-", "UNL-", "BMC-", "BTL-"]
+        .iter()
+        .find(|prefix| board.starts_with(*prefix))
+        .map_or(board, |prefix| &board[prefix.len()..]);
+
+    let mapper = match name {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, 0),
+        "SAROM" | "SBROM" | "SCROM" | "SEROM" | "SFROM" | "SGROM" | "SHROM" | "SJROM"
+        | "SKROM" | "SLROM" | "SL1ROM" | "SNROM" | "SOROM" | "SUROM" | "SXROM" => {
+            // boards with only 32K of PRG-ROM
+            if name == "SEROM" || name == "SHROM" {
+                (1, 5)
+            } else {
+                (1, 0)
+            }
+        }
+        "UNROM" | "UOROM" => (2, 0),
+        "CNROM" => (3, 0),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TNROM" | "TR1ROM"
+        | "TSROM" | "TVROM" | "B4" => (4, 0),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, 0),
+        "AMROM" | "ANROM" | "AN1ROM" | "AOROM" => (7, 0),
+        "PEEOROM" | "PNROM" => (9, 0),
+        "CPROM" => (13, 0),
+        "BNROM" => (34, 0),
+        "GNROM" | "MHROM" => (66, 0),
+        "H2288" => (123, 0),
+        "SA-72008" => (133, 0),
+        "Sachen-8259D" => (137, 0),
+        "Sachen-8259B" => (138, 0),
+        "Sachen-8259C" => (139, 0),
+        "Sachen-8259A" => (141, 0),
+        "SA-72007" => (145, 0),
+        "TC-U01-1.5M" => (147, 0),
+        "Sachen-74LS374N" => (150, 0),
+        "NovelDiamond9999999in1" => (201, 0),
+        "8237" => (215, 0),
+        "70in1" => (236, 0),
+        "KOF97" => (263, 0),
+        "T-262" => (265, 0),
+        "GS-2004" | "GS-2013" => (283, 0),
+        "SMB2J" => (304, 0),
+        "12-IN-1" => (331, 0),
+        _ => return None,
+    };
+
+    Some(mapper)
+}
@@ -0,0 +1,172 @@
+#[cfg(test)]
+mod tests {
+    use nes::asm;
+    use nes::asm::Segment;
+    use nes::cpu::Cpu;
+    use nes::disasm;
+    use nes::disasm::OPCODES;
+    use nes::memory::FlatMemory;
+    use nes::memory::Memory;
+
+    fn assemble(source: &str) -> Vec<u8> {
+        let program = asm::assemble(source).unwrap();
+        assert!(program.segments.len() == 1);
+        program.segments[0].bytes.clone()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        assert!(assemble("lda #$10") == vec![0xa9, 0x10]);
+        assert!(assemble("LDA $10") == vec![0xa5, 0x10]);
+        assert!(assemble("LDA $10,X") == vec![0xb5, 0x10]);
+        assert!(assemble("LDX $10,Y") == vec![0xb6, 0x10]);
+        assert!(assemble("LDA $0010") == vec![0xa5, 0x10]);
+        assert!(assemble("LDA $1234") == vec![0xad, 0x34, 0x12]);
+        assert!(assemble("LDA $1234,x") == vec![0xbd, 0x34, 0x12]);
+        assert!(assemble("LDA $10,Y") == vec![0xb9, 0x10, 0x00]);
+        assert!(assemble("LDA ($10,X)") == vec![0xa1, 0x10]);
+        assert!(assemble("LDA ($10), Y") == vec![0xb1, 0x10]);
+        assert!(assemble("JMP ($1234)") == vec![0x6c, 0x34, 0x12]);
+        assert!(assemble("ASL") == vec![0x0a]);
+        assert!(assemble("ASL A") == vec![0x0a]);
+        assert!(assemble("NOP") == vec![0xea]);
+        assert!(assemble("SBC #1") == vec![0xe9, 0x01]);
+        assert!(assemble("ISB $10") == vec![0xe7, 0x10]);
+    }
+
+    #[test]
+    fn test_labels_and_branches() {
+        let program = asm::assemble(
+            "
+            .org $0200
+            start:  LDX #%00000011  ; count down from 3
+            loop:   DEX
+                    BNE loop
+                    BEQ done
+                    .byte 1, 2
+            done:   JMP start
+            ",
+        )
+        .unwrap();
+
+        assert!(program.label("start") == Some(0x0200));
+        assert!(program.label("loop") == Some(0x0202));
+        assert!(program.label("done") == Some(0x0209));
+        assert!(
+            program.segments
+                == vec![Segment {
+                    origin: 0x0200,
+                    bytes: vec![
+                        0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xf0, 0x02, 0x01, 0x02, 0x4c, 0x00, 0x02,
+                    ],
+                }]
+        );
+    }
+
+    #[test]
+    fn test_forward_references_are_absolute() {
+        let bytes = assemble(
+            "
+            LDA value
+            value: .byte $42
+            ",
+        );
+
+        assert!(bytes == vec![0xad, 0x03, 0x00, 0x42]);
+    }
+
+    #[test]
+    fn test_directives() {
+        let program = asm::assemble(
+            "
+            .org $fffa
+            .word nmi, reset, irq
+            .org $8000
+            reset: LDA #<table+1
+                   LDA #>table+1
+            nmi:
+            irq:   RTI
+            table: .byte $ff
+            ",
+        )
+        .unwrap();
+
+        assert!(program.segments.len() == 2);
+        assert!(program.segments[0].origin == 0xfffa);
+        assert!(program.segments[0].bytes == vec![0x04, 0x80, 0x00, 0x80, 0x04, 0x80]);
+        assert!(program.segments[1].origin == 0x8000);
+        assert!(program.segments[1].bytes == vec![0xa9, 0x06, 0xa9, 0x80, 0x40, 0xff]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(asm::assemble("FOO").unwrap_err().line == 1);
+        assert!(asm::assemble("NOP\nLDA ($1234),Y").unwrap_err().line == 2);
+        assert!(asm::assemble("LDA missing").is_err());
+        assert!(asm::assemble("STX $1234,Y").is_err());
+        assert!(asm::assemble(".byte $100").is_err());
+        assert!(asm::assemble("a: NOP\na: NOP").is_err());
+        assert!(asm::assemble(".org $0200\nBNE $0300").is_err());
+        assert!(asm::assemble("LDA é").is_err());
+        assert!(asm::assemble("LDA #é+1").is_err());
+        // the last instruction would wrap around to $0000
+        assert!(asm::assemble(".org $fffe\nJMP $1234").unwrap_err().line == 2);
+        assert!(asm::assemble(".org $fffd\nJMP $1234").is_ok());
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let mut memory = FlatMemory::new();
+
+        for (opcode, info) in OPCODES.iter().enumerate() {
+            memory.store(0x0200, opcode as u8);
+            memory.store(0x0201, 0x34);
+            memory.store(0x0202, 0x12);
+
+            let (text, length) = disasm::disassemble_instruction(&memory, 0x0200);
+            let bytes = asm::assemble(&format!(".org $0200\n{}", text)).unwrap().segments[0]
+                .bytes
+                .clone();
+
+            // unofficial duplicates assemble to the first opcode with the same mnemonic and mode
+            let expected = (0..256)
+                .filter(|&i| {
+                    OPCODES[i].mnemonic == info.mnemonic && OPCODES[i].mode == info.mode
+                })
+                .min_by_key(|&i| !OPCODES[i].official)
+                .unwrap();
+
+            assert!(bytes.len() == length as usize);
+            assert!(bytes[0] == expected as u8);
+            assert!(bytes[1..] == [0x34, 0x12][..length as usize - 1]);
+        }
+    }
+
+    #[test]
+    fn test_run_assembled_program() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        let program = asm::assemble(
+            "
+            .org $0200
+                    LDX #5
+                    LDA #0
+                    CLC
+            loop:   ADC #3
+                    DEX
+                    BNE loop
+                    STA $10
+            ",
+        )
+        .unwrap();
+        program.load(&mut cpu.memory);
+        cpu.registers.program_counter = 0x0200;
+
+        for _ in 0..19 {
+            cpu.execute_instruction();
+        }
+
+        assert!(cpu.memory.load(0x0010) == 15);
+        assert!(cpu.registers.program_counter == 0x020c);
+    }
+}
@@ -0,0 +1,294 @@
+#[cfg(test)]
+mod tests {
+    use nes::asm;
+    use nes::console::Nes;
+    use nes::error::Error;
+    use nes::memory::{Memory, RamPattern};
+    use nes::rom::Rom;
+
+    use std::io::Cursor;
+
+    /// Builds an NROM cartridge with 16K of PRG-ROM at $C000 from assembly source
+    fn cartridge(source: &str) -> Rom {
+        cartridge_with_flags(source, 0)
+    }
+
+    fn cartridge_with_flags(source: &str, flags_6: u8) -> Rom {
+        let program = asm::assemble(source).unwrap();
+        let mut prg = vec![0u8; 16384];
+        for segment in &program.segments {
+            for (i, byte) in segment.bytes.iter().enumerate() {
+                prg[(segment.origin as usize + i) & 0x3fff] = *byte;
+            }
+        }
+
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 1, 1, flags_6, 0];
+        image.resize(16, 0);
+        image.extend(prg);
+        image.resize(16 + 16384 + 8192, 0);
+
+        Rom::load(&mut Cursor::new(image)).unwrap()
+    }
+
+    /// Program with the given code at reset. IRQs return right away, and so do NMIs after
+    /// running the given handler.
+    fn program(reset: &str, nmi: &str) -> String {
+        format!(
+            "
+            .org $fffa
+            .word nmi, reset, irq
+            .org $c000
+            reset: {}
+            nmi:   {}
+            irq:   RTI
+            ",
+            reset, nmi
+        )
+    }
+
+    /// Powers on a console with a cartridge running the given code at reset
+    fn nes_with(reset: &str) -> Nes {
+        Nes::new(cartridge(&program(reset, ""))).unwrap()
+    }
+
+    #[test]
+    fn test_power_on() {
+        let nes = nes_with("JMP reset");
+
+        assert!(nes.cpu.registers.program_counter == 0xc000);
+        assert!(nes.cpu.memory.ppu.scanline == 0);
+        assert!(nes.cpu.memory.ppu.frame == 0);
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut nes = nes_with("JMP reset");
+
+        nes.run_frame().unwrap();
+        assert!(nes.cpu.memory.ppu.frame == 1);
+        assert!(nes.cpu.memory.ppu.scanline == 241);
+        assert!(nes.cpu.memory.ppu.in_vblank());
+
+        // a frame is 341 * 262 dots, 29780.67 cpu cycles
+        let cycle = nes.cpu.cycle;
+        nes.run_frame().unwrap();
+        let cycles = nes.cpu.cycle - cycle;
+        assert!(cycles == 29780 || cycles == 29781);
+    }
+
+    #[test]
+    fn test_trace_ppu_position() {
+        let nes = nes_with("JMP reset");
+
+        // the PPU ran along with the reset sequence, as in nestest.log
+        assert!(nes.cpu.trace_line().ends_with("P:34 SP:FD PPU:  0, 21 CYC:7"));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut nes = nes_with(
+            "LDX #1
+             INX
+             JMP reset",
+        );
+
+        assert!(nes.step_instruction().unwrap() == 2);
+        assert!(nes.step_instruction().unwrap() == 2);
+        assert!(nes.cpu.registers.x == 2);
+
+        let cycle = nes.cpu.cycle;
+        let dot = nes.cpu.memory.ppu.dot;
+        nes.step_cycles(10).unwrap();
+        assert!(nes.cpu.cycle == cycle + 10);
+        assert!(nes.cpu.memory.ppu.dot == dot + 30);
+    }
+
+    #[test]
+    fn test_vblank_nmi() {
+        let mut nes = Nes::new(cartridge(&program(
+            "LDA #$80
+             STA $2000
+             loop: JMP loop",
+            "INC $10
+             LDA $2002",
+        )))
+        .unwrap();
+
+        nes.run_frame().unwrap();
+        nes.step_cycles(100).unwrap();
+        assert!(nes.cpu.memory.load(0x0010) == 1);
+        // the handler acknowledged vblank
+        assert!(!nes.cpu.memory.ppu.in_vblank());
+
+        nes.run_frame().unwrap();
+        nes.run_frame().unwrap();
+        nes.step_cycles(100).unwrap();
+        assert!(nes.cpu.memory.load(0x0010) == 3);
+    }
+
+    #[test]
+    fn test_vblank_without_nmi() {
+        let mut nes = Nes::new(cartridge(&program("JMP reset", "INC $10"))).unwrap();
+
+        nes.run_frame().unwrap();
+        nes.step_cycles(100).unwrap();
+
+        assert!(nes.cpu.memory.load(0x0010) == 0);
+        assert!(nes.cpu.memory.ppu.in_vblank());
+    }
+
+    #[test]
+    fn test_jam() {
+        let mut nes = nes_with(
+            "NOP
+             KIL",
+        );
+
+        nes.step_instruction().unwrap();
+        assert!(matches!(
+            nes.step_instruction(),
+            Err(Error::CpuJam {
+                opcode: 0x02,
+                address: 0xc001,
+            })
+        ));
+        assert!(nes.run_frame().is_err());
+
+        // the rest of the console keeps running
+        let dot = nes.cpu.memory.ppu.dot;
+        assert!(nes.step_cycles(5).is_err());
+        assert!(nes.cpu.memory.ppu.dot == dot + 15);
+
+        nes.reset();
+        assert!(nes.step_instruction().is_ok());
+    }
+
+    #[test]
+    fn test_reset_keeps_ram_and_registers() {
+        let mut nes = nes_with(
+            "LDX #$42
+             STX $10
+             loop: JMP loop",
+        );
+
+        nes.step_instruction().unwrap();
+        nes.step_instruction().unwrap();
+        assert!(nes.cpu.registers.stack_pointer == 0xfd);
+
+        nes.reset();
+
+        assert!(nes.cpu.memory.load(0x0010) == 0x42);
+        assert!(nes.cpu.registers.x == 0x42);
+        assert!(nes.cpu.registers.stack_pointer == 0xfa);
+        assert!(nes.cpu.registers.program_counter == 0xc000);
+
+        nes.power_cycle();
+
+        assert!(nes.cpu.memory.load(0x0010) == 0x00);
+        assert!(nes.cpu.registers.x == 0x00);
+        assert!(nes.cpu.registers.stack_pointer == 0xfd);
+        assert!(nes.cpu.registers.processor_status == 0x34);
+    }
+
+    #[test]
+    fn test_ram_pattern() {
+        let source = program("JMP reset", "");
+
+        let mut nes = Nes::with_ram_pattern(cartridge(&source), RamPattern::Ones).unwrap();
+        assert!(nes.cpu.memory.load(0x0000) == 0xff);
+        assert!(nes.cpu.memory.load(0x07ff) == 0xff);
+
+        let mut random = Nes::with_ram_pattern(cartridge(&source), RamPattern::Random(1)).unwrap();
+        let mut again = Nes::with_ram_pattern(cartridge(&source), RamPattern::Random(1)).unwrap();
+        let ram: Vec<u8> = (0..0x800).map(|address| random.cpu.memory.load(address)).collect();
+
+        assert!(ram.iter().any(|&value| value != ram[0]));
+        assert!((0..0x800).all(|address| again.cpu.memory.load(address) == ram[address as usize]));
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut nes = nes_with("JMP reset");
+        let bus = &mut nes.cpu.memory;
+
+        // 2K of RAM mirrored 4 times
+        bus.store(0x0010, 0x42);
+        assert!(bus.load(0x0810) == 0x42);
+        assert!(bus.load(0x1810) == 0x42);
+        bus.store(0x1fff, 0x24);
+        assert!(bus.load(0x07ff) == 0x24);
+
+        // PPU registers mirrored every 8 bytes
+        bus.ppu.scanline = 240;
+        bus.ppu.dot = 340;
+        bus.ppu.tick();
+        bus.ppu.tick();
+        assert!(bus.peek(0x3ffa) & 0x80 != 0);
+        assert!(bus.load(0x200a) & 0x80 != 0);
+        assert!(bus.load(0x2002) & 0x80 == 0);
+
+        // cartridge space
+        assert!(bus.load(0xfffc) == 0x00);
+        assert!(bus.load(0xfffd) == 0xc0);
+        assert!(bus.load(0xbffd) == 0xc0);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut nes = nes_with(
+            "LDA $5000    ; unmapped, the last byte on the bus was the operand's $50
+             STA $00
+             LDA $4018
+             STA $01
+             LDX $4016    ; controller port, bits 5-7 are open bus
+             STX $02
+             LDA #$ee
+             STA $2000
+             LDA $2002    ; unused PPUSTATUS bits come from the PPU's latch
+             STA $03
+             loop: JMP loop",
+        );
+
+        for _ in 0..10 {
+            nes.step_instruction().unwrap();
+        }
+
+        assert!(nes.cpu.memory.peek(0x0000) == 0x50);
+        assert!(nes.cpu.memory.peek(0x0001) == 0x40);
+        assert!(nes.cpu.memory.peek(0x0002) == 0x40);
+        assert!(nes.cpu.memory.peek(0x0003) == 0x0e);
+        assert!(nes.cpu.memory.open_bus() == 0x0e);
+    }
+
+    #[test]
+    fn test_battery_save() {
+        let source = program(
+            "INC $6000
+             LDA $6000
+             STA $7fff
+             loop: JMP loop",
+            "",
+        );
+
+        let mut nes = Nes::new(cartridge_with_flags(&source, 0x02)).unwrap();
+        nes.load_save(&mut Cursor::new(vec![0x41])).unwrap();
+        nes.run_frame().unwrap();
+
+        let mut save = Vec::new();
+        nes.write_save(&mut save).unwrap();
+        assert!(save.len() == 8192);
+        assert!(save[0] == 0x42);
+        assert!(save[0x1fff] == 0x42);
+
+        // PRG-RAM survives a power cycle, as it would on the cartridge's battery
+        nes.power_cycle();
+        nes.run_frame().unwrap();
+        assert!(nes.cpu.memory.peek(0x6000) == 0x43);
+
+        // without a battery there is nothing to save
+        let nes = Nes::new(cartridge(&source)).unwrap();
+        let mut save = Vec::new();
+        nes.write_save(&mut save).unwrap();
+        assert!(save.is_empty());
+    }
+}
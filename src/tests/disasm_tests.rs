@@ -0,0 +1,80 @@
+#[cfg(test)]
+mod tests {
+    use nes::disasm;
+    use nes::disasm::{AddressingMode, Index, OPCODES};
+    use nes::memory::FlatMemory;
+    use nes::memory::Memory;
+
+    fn load(memory: &mut FlatMemory, address: u16, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            memory.store(address + i as u16, *byte);
+        }
+    }
+
+    #[test]
+    fn test_opcode_table() {
+        assert!(OPCODES.iter().filter(|opcode| opcode.official).count() == 151);
+
+        let lda = OPCODES[0xbd];
+        assert!(lda.mnemonic == "LDA");
+        assert!(lda.mode == AddressingMode::AbsoluteIndexed(Index::X));
+        assert!(lda.length == 3);
+        assert!(lda.cycles == 4);
+        assert!(lda.official);
+
+        let sbc = OPCODES[0xeb];
+        assert!(sbc.mnemonic == "SBC");
+        assert!(sbc.mode == AddressingMode::Immediate);
+        assert!(!sbc.official);
+
+        assert!(OPCODES[0x6c].mode == AddressingMode::Indirect);
+        assert!(OPCODES[0xb6].mode == AddressingMode::ZeroPageIndexed(Index::Y));
+        assert!(OPCODES[0x0a].mode == AddressingMode::Accumulator);
+        assert!(OPCODES[0x00].length == 1);
+        assert!(OPCODES[0x00].cycles == 7);
+        assert!(OPCODES[0x02].cycles == 0);
+    }
+
+    #[test]
+    fn test_disassemble_instruction() {
+        let mut memory = FlatMemory::new();
+
+        load(&mut memory, 0x0200, &[0xb1, 0x12]);
+        assert!(disasm::disassemble_instruction(&memory, 0x0200) == ("LDA ($12),Y".to_string(), 2));
+
+        load(&mut memory, 0x0200, &[0x6c, 0xff, 0x02]);
+        assert!(disasm::disassemble_instruction(&memory, 0x0200) == ("JMP ($02FF)".to_string(), 3));
+
+        load(&mut memory, 0x0200, &[0x96, 0x10]);
+        assert!(disasm::disassemble_instruction(&memory, 0x0200) == ("STX $10,Y".to_string(), 2));
+
+        load(&mut memory, 0x0200, &[0x4a]);
+        assert!(disasm::disassemble_instruction(&memory, 0x0200) == ("LSR A".to_string(), 1));
+    }
+
+    #[test]
+    fn test_disassemble_range() {
+        let mut memory = FlatMemory::new();
+
+        // loop: DEX, BNE loop, *NOP $10, RTS
+        load(&mut memory, 0xc000, &[0xca, 0xd0, 0xfd, 0x04, 0x10, 0x60]);
+
+        let text = disasm::disassemble(&memory, 0xc000, 0xc005);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines.len() == 4);
+        assert!(lines[0] == "C000  CA        DEX");
+        assert!(lines[1] == "C001  D0 FD     BNE $C000");
+        assert!(lines[2] == "C003  04 10    *NOP $10");
+        assert!(lines[3] == "C005  60        RTS");
+    }
+
+    #[test]
+    fn test_disassemble_end_of_memory() {
+        let mut memory = FlatMemory::new();
+
+        load(&mut memory, 0xfffe, &[0xea, 0xea]);
+
+        assert!(disasm::disassemble(&memory, 0xfffe, 0xffff).lines().count() == 2);
+    }
+}
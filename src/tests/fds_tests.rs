@@ -0,0 +1,239 @@
+#[cfg(test)]
+mod tests {
+    use nes::asm;
+    use nes::console::Nes;
+    use nes::error::Error;
+    use nes::fds::{self, SIDE_SIZE};
+    use nes::mapper::{create_mapper, Mapper, Mirroring};
+    use nes::memory::Memory;
+    use nes::rom::Rom;
+
+    use std::io::Cursor;
+
+    /// One side holding a disk info block, a file amount block and a 4 byte file, followed by
+    /// a second blank side
+    fn disk() -> Vec<u8> {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(56, 0);
+        side.extend_from_slice(&[0x02, 0x01]);
+        let mut file_header = vec![0x03; 16];
+        file_header[13] = 4;
+        file_header[14] = 0;
+        side.extend(file_header);
+        side.extend_from_slice(&[0x04, 0xde, 0xad, 0xbe, 0xef]);
+        side.resize(SIDE_SIZE, 0);
+
+        let mut disk = side.clone();
+        disk.extend(b"\x01*NINTENDO-HVC*");
+        disk.resize(2 * SIDE_SIZE, 0);
+        disk
+    }
+
+    /// BIOS assembled from source, mapped at $E000-$FFFF
+    fn bios(source: &str) -> Vec<u8> {
+        let program = asm::assemble(source).unwrap();
+        let mut bios = vec![0u8; fds::BIOS_SIZE];
+        for segment in &program.segments {
+            for (i, byte) in segment.bytes.iter().enumerate() {
+                bios[(segment.origin as usize + i) & 0x1fff] = *byte;
+            }
+        }
+        bios
+    }
+
+    fn load(bios: Vec<u8>) -> Rom {
+        Rom::load_fds(&mut Cursor::new(disk()), &mut Cursor::new(bios)).unwrap()
+    }
+
+    fn mapper() -> Box<dyn Mapper> {
+        create_mapper(Box::new(load(vec![0; fds::BIOS_SIZE]))).unwrap()
+    }
+
+    /// Runs the drive until the next byte is transferred and reads it
+    fn next_byte(mapper: &mut Box<dyn Mapper>) -> u8 {
+        for _ in 0..1_000_000 {
+            mapper.tick();
+            if mapper.prg_load(0x4030).unwrap() & 0x02 != 0 {
+                return mapper.prg_read(0x4031).unwrap();
+            }
+        }
+        panic!("no byte transferred");
+    }
+
+    #[test]
+    fn test_load() {
+        let rom = load(vec![0xea; fds::BIOS_SIZE]);
+        assert!(rom.header.mapper() == 20);
+        assert!(rom.header.mapper_name() == Some("FDS"));
+        assert!(rom.header.prg_ram_bytes() == 32768);
+        assert!(rom.disk.as_ref().map(|disk| disk.sides.len()) == Some(2));
+
+        // the fwNES header is optional
+        let mut image = b"FDS\x1a\x02".to_vec();
+        image.resize(16, 0);
+        image.extend(disk());
+        let rom = Rom::load_fds(&mut Cursor::new(image), &mut Cursor::new(vec![0; 8192])).unwrap();
+        assert!(rom.disk.as_ref().map(|disk| disk.sides.len()) == Some(2));
+
+        assert!(matches!(Rom::load(&mut Cursor::new(disk())), Err(Error::MissingBios)));
+        let rom = Rom::load_fds(&mut Cursor::new(disk()), &mut Cursor::new(vec![0; 1024]));
+        assert!(matches!(rom, Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut mapper = create_mapper(Box::new(load(vec![0xea; fds::BIOS_SIZE]))).unwrap();
+
+        mapper.prg_store(0x6000, 0x12);
+        mapper.prg_store(0xdfff, 0x34);
+        assert!(mapper.prg_load(0x6000) == Some(0x12));
+        assert!(mapper.prg_load(0xdfff) == Some(0x34));
+        assert!(mapper.prg_load(0xe000) == Some(0xea));
+        mapper.prg_store(0xe000, 0x56);
+        assert!(mapper.prg_load(0xe000) == Some(0xea));
+        assert!(mapper.prg_load(0x5000).is_none());
+
+        mapper.prg_store(0x4023, 0x01);
+        mapper.prg_store(0x4025, 0x2e);
+        assert!(mapper.mirroring() == Mirroring::Horizontal);
+        mapper.prg_store(0x4025, 0x26);
+        assert!(mapper.mirroring() == Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_chr_ram() {
+        let mut mapper = mapper();
+
+        // 8K of CHR-RAM, mirrored through the whole PPU address space
+        mapper.chr_store(0x1234, 0x56);
+        assert!(mapper.chr_load(0x1234) == 0x56);
+        assert!(mapper.chr_load(0x3234) == 0x56);
+        mapper.chr_store(0x3fff, 0x78);
+        assert!(mapper.chr_load(0x1fff) == 0x78);
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        let mut mapper = mapper();
+
+        // the timer needs the disk registers enabled
+        mapper.prg_store(0x4020, 10);
+        mapper.prg_store(0x4021, 0);
+        mapper.prg_store(0x4022, 0x02);
+        mapper.tick();
+        assert!(!mapper.irq());
+
+        mapper.prg_store(0x4023, 0x01);
+        mapper.prg_store(0x4022, 0x02);
+        for _ in 0..10 {
+            mapper.tick();
+        }
+        assert!(!mapper.irq());
+        mapper.tick();
+        assert!(mapper.irq());
+
+        // reading $4030 acknowledges it, and without repeat the timer stops
+        assert!(mapper.prg_read(0x4030).unwrap() & 0x01 != 0);
+        assert!(!mapper.irq());
+        for _ in 0..100 {
+            mapper.tick();
+        }
+        assert!(!mapper.irq());
+
+        mapper.prg_store(0x4022, 0x03);
+        for _ in 0..22 {
+            mapper.tick();
+        }
+        assert!(mapper.irq());
+        mapper.prg_store(0x4023, 0x00);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_read_disk() {
+        let mut mapper = mapper();
+
+        // disk inserted, not scanning yet
+        mapper.prg_store(0x4023, 0x01);
+        assert!(mapper.prg_load(0x4032).unwrap() & 0x07 == 0x02);
+
+        // motor on, read mode, transfer enabled with IRQs
+        mapper.prg_store(0x4025, 0xe5);
+        assert!(next_byte(&mut mapper) == 0x80);
+        assert!(!mapper.irq());
+        let verification: Vec<u8> = (0..15).map(|_| next_byte(&mut mapper)).collect();
+        assert!(&verification[..] == b"\x01*NINTENDO-HVC*");
+        assert!(mapper.prg_load(0x4032).unwrap() & 0x07 == 0x00);
+
+        // every byte after the start mark raises an IRQ, acknowledged by reading $4031
+        mapper.tick();
+        while !mapper.irq() {
+            mapper.tick();
+        }
+        assert!(mapper.prg_read(0x4031) == Some(0));
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn test_insert_disk() {
+        let mut nes = Nes::new(load(bios(
+            "
+            .org $e000
+            reset: JMP reset
+            nmi:
+            irq:   RTI
+            .org $fffa
+            .word nmi, reset, irq
+            ",
+        )))
+        .unwrap();
+
+        assert!(nes.disk_sides() == 2);
+        assert!(nes.disk_side() == Some(0));
+        assert!(matches!(nes.insert_disk(Some(2)), Err(Error::NoSuchDiskSide(2))));
+
+        nes.insert_disk(None).unwrap();
+        assert!(nes.disk_side().is_none());
+        assert!(nes.cpu.memory.mapper().prg_load(0x4032).unwrap() & 0x01 != 0);
+
+        // the drive looks empty for a while before the new side shows up
+        nes.insert_disk(Some(1)).unwrap();
+        assert!(nes.disk_side() == Some(1));
+        nes.run_frame().unwrap();
+        assert!(nes.cpu.memory.mapper().prg_load(0x4032).unwrap() & 0x01 != 0);
+        for _ in 0..60 {
+            nes.run_frame().unwrap();
+        }
+        assert!(nes.cpu.memory.mapper().prg_load(0x4032).unwrap() & 0x01 == 0);
+    }
+
+    #[test]
+    fn test_console_timer_irq() {
+        let mut nes = Nes::new(load(bios(
+            "
+            .org $e000
+            reset: LDA #$01
+                   STA $4023
+                   LDA #$e8
+                   STA $4020
+                   LDA #$03
+                   STA $4021
+                   LDA #$03
+                   STA $4022
+                   CLI
+            loop:  JMP loop
+            irq:   INC $00
+                   LDA $4030
+            nmi:   RTI
+            .org $fffa
+            .word nmi, reset, irq
+            ",
+        )))
+        .unwrap();
+
+        // one IRQ every 1001 cycles
+        nes.run_frame().unwrap();
+        let irqs = nes.cpu.memory.peek(0x00);
+        assert!((26..=28).contains(&irqs), "{} IRQs", irqs);
+    }
+}
@@ -1,16 +1,65 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
     use nes::cpu;
     use nes::cpu::Cpu;
+    use nes::cpu::CpuState;
     use nes::cpu::Interrupt;
+    use nes::cpu::IrqSource;
+    use nes::error::Error;
+    use nes::memory::FlatMemory;
     use nes::memory::Memory;
-    use nes::memory::NesMemory;
+
+    // Power on and reset
+
+    #[test]
+    fn test_power_on() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0x12;
+        cpu.memory.store(0xfffc, 0x00);
+        cpu.memory.store(0xfffd, 0x80);
+
+        cpu.power_on();
+
+        // the reset sequence runs on the next ticks
+        assert!(cpu.cycle == 0);
+        assert!(cpu.do_interrupts() == 7);
+
+        assert!(cpu.registers.accumulator == 0);
+        assert!(cpu.registers.stack_pointer == 0xfd);
+        assert!(cpu.registers.processor_status == 0x34);
+        assert!(cpu.registers.program_counter == 0x8000);
+        assert!(cpu.cycle == 7);
+    }
+
+    #[test]
+    fn test_reset_keeps_registers() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.power_on();
+
+        cpu.registers.accumulator = 0x12;
+        cpu.registers.x = 0x34;
+        cpu.registers.stack_pointer = 0x80;
+        cpu.registers.processor_status = 0x21;
+        cpu.memory.store(0xfffc, 0x00);
+        cpu.memory.store(0xfffd, 0x80);
+
+        cpu.reset();
+        assert!(cpu.registers.program_counter != 0x8000);
+        assert!(cpu.do_interrupts() == 7);
+
+        assert!(cpu.registers.accumulator == 0x12);
+        assert!(cpu.registers.x == 0x34);
+        assert!(cpu.registers.stack_pointer == 0x7d);
+        assert!(cpu.registers.processor_status == 0x25);
+        assert!(cpu.registers.program_counter == 0x8000);
+    }
 
     // LDA
     #[test]
     fn test_lda_immediate() {
-        let mut cpu: Cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x010;
 
@@ -24,7 +73,7 @@ mod tests {
 
     #[test]
     fn test_lda_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -39,7 +88,7 @@ mod tests {
 
     #[test]
     fn test_lda_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
         cpu.registers.x = 0x01;
@@ -55,7 +104,7 @@ mod tests {
 
     #[test]
     fn test_lda_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -71,7 +120,7 @@ mod tests {
 
     #[test]
     fn test_lda_absolute_x_and_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // X
         cpu.registers.program_counter = 0x0100;
@@ -104,7 +153,7 @@ mod tests {
 
     #[test]
     fn test_lda_indirect_x_and_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // X
         cpu.registers.program_counter = 0x0100;
@@ -141,7 +190,7 @@ mod tests {
 
     #[test]
     fn test_ldx_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -155,7 +204,7 @@ mod tests {
 
     #[test]
     fn test_ldx_zero_page_x_and_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // X
         cpu.registers.program_counter = 0x0100;
@@ -185,7 +234,7 @@ mod tests {
 
     #[test]
     fn test_ldx_absolute_x_and_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // X
         cpu.registers.program_counter = 0x0100;
@@ -219,7 +268,7 @@ mod tests {
 
     #[test]
     fn test_ldy_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -233,7 +282,7 @@ mod tests {
 
     #[test]
     fn test_ldy_zero_page_y_and_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // Y
         cpu.registers.program_counter = 0x0100;
@@ -264,7 +313,7 @@ mod tests {
     #[test]
     fn test_ldy_absolute_y_and_x()
     {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // Y
         cpu.registers.program_counter = 0x0100;
@@ -298,7 +347,7 @@ mod tests {
 
     #[test]
     fn test_sta_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -313,7 +362,7 @@ mod tests {
 
     #[test]
     fn test_sta_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -329,7 +378,7 @@ mod tests {
 
     #[test]
     fn test_sta_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -345,7 +394,7 @@ mod tests {
 
     #[test]
     fn test_sta_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -362,7 +411,7 @@ mod tests {
 
     #[test]
     fn test_sta_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 0x01;
@@ -379,7 +428,7 @@ mod tests {
 
     #[test]
     fn test_sta_indirect_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -397,7 +446,7 @@ mod tests {
 
     #[test]
     fn test_sta_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 0x01;
@@ -417,7 +466,7 @@ mod tests {
 
     #[test]
     fn test_stx_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -432,7 +481,7 @@ mod tests {
 
     #[test]
     fn test_stx_zero_page_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.y = 0x01;
@@ -448,7 +497,7 @@ mod tests {
 
     #[test]
     fn test_stx_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -466,7 +515,7 @@ mod tests {
 
     #[test]
     fn test_sty_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -481,7 +530,7 @@ mod tests {
 
     #[test]
     fn test_sty_zero_page_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.x = 0x01;
@@ -497,7 +546,7 @@ mod tests {
 
     #[test]
     fn test_sty_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -516,7 +565,7 @@ mod tests {
     #[test]
     fn test_tax()
     {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -532,7 +581,7 @@ mod tests {
 
     #[test]
     fn test_tay() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -548,7 +597,7 @@ mod tests {
 
     #[test]
     fn test_txa() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -564,7 +613,7 @@ mod tests {
 
     #[test]
     fn test_tya() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -580,7 +629,7 @@ mod tests {
 
     #[test]
     fn test_tsx() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
         cpu.registers.stack_pointer = 0xff;
@@ -596,26 +645,30 @@ mod tests {
 
     #[test]
     fn test_txs() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
+        cpu.registers.processor_status = 0x26;
 
         cpu.memory.store(0x0100, 0x9a);
 
         cpu.execute_instruction();
 
         assert!(cpu.registers.stack_pointer == 0xff);
+        // unlike the other transfers, TXS leaves the flags alone
+        assert!(cpu.registers.processor_status == 0x26);
     }
 
     // PHA
 
     #[test]
     fn test_pha() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // reset registers (just so stack pointer isn't allowed to underflow)
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -631,10 +684,11 @@ mod tests {
 
     #[test]
     fn test_php() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // see test_pha
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.processor_status = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -650,10 +704,11 @@ mod tests {
 
     #[test]
     fn test_pla() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // see test_pha
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.program_counter = 0x0100;
         cpu.push(0xff);
@@ -669,10 +724,11 @@ mod tests {
 
     #[test]
     fn test_plp() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         // set test_pha
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.program_counter = 0x0100;
         cpu.push(0xff);
@@ -688,7 +744,7 @@ mod tests {
 
     #[test]
     fn test_and_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -703,7 +759,7 @@ mod tests {
 
     #[test]
     fn test_and_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -719,7 +775,7 @@ mod tests {
 
     #[test]
     fn test_and_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x0ff;
         cpu.registers.x = 0x01;
@@ -736,7 +792,7 @@ mod tests {
 
     #[test]
     fn test_and_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -753,7 +809,7 @@ mod tests {
 
     #[test]
     fn test_and_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 1;
@@ -771,7 +827,7 @@ mod tests {
 
     #[test]
     fn test_and_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 1;
@@ -789,7 +845,7 @@ mod tests {
 
     #[test]
     fn test_and_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 1;
@@ -810,7 +866,7 @@ mod tests {
 
     #[test]
     fn test_eor_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -826,7 +882,7 @@ mod tests {
 
     #[test]
     fn test_eor_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -842,7 +898,7 @@ mod tests {
 
     #[test]
     fn test_eor_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -859,7 +915,7 @@ mod tests {
 
     #[test]
     fn test_eor_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
 
@@ -875,7 +931,7 @@ mod tests {
 
     #[test]
     fn test_eor_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -893,7 +949,7 @@ mod tests {
 
     #[test]
     fn test_eor_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 0x01;
@@ -911,7 +967,7 @@ mod tests {
 
     #[test]
     fn test_eor_indirect_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -930,7 +986,7 @@ mod tests {
 
     #[test]
     fn test_eor_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 0x01;
@@ -951,7 +1007,7 @@ mod tests {
 
     #[test]
     fn test_ora_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.program_counter = 0x0100;
@@ -966,7 +1022,7 @@ mod tests {
 
     #[test]
     fn test_ora_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.program_counter = 0x0100;
@@ -982,7 +1038,7 @@ mod tests {
 
     #[test]
     fn test_ora_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.x = 0x01;
@@ -999,7 +1055,7 @@ mod tests {
 
     #[test]
     fn test_ora_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.program_counter = 0x0100;
@@ -1016,7 +1072,7 @@ mod tests {
 
     #[test]
     fn test_ora_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.x = 0x01;
@@ -1034,7 +1090,7 @@ mod tests {
 
     #[test]
     fn test_ora_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.y = 0x01;
@@ -1052,7 +1108,7 @@ mod tests {
 
     #[test]
     fn test_ora_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xf0;
         cpu.registers.y = 0x01;
@@ -1073,7 +1129,7 @@ mod tests {
 
     #[test]
     fn test_bit_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1089,7 +1145,7 @@ mod tests {
 
     #[test]
     fn test_bit_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1108,7 +1164,7 @@ mod tests {
 
     #[test]
     fn test_adc_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1123,7 +1179,7 @@ mod tests {
 
     #[test]
     fn test_adc_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1139,7 +1195,7 @@ mod tests {
 
     #[test]
     fn test_adc_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.x = 0x01;
@@ -1156,7 +1212,7 @@ mod tests {
 
     #[test]
     fn test_adc_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1173,7 +1229,7 @@ mod tests {
 
     #[test]
     fn test_adc_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.x = 0x01;
@@ -1191,7 +1247,7 @@ mod tests {
 
     #[test]
     fn test_adc_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.y = 0x01;
@@ -1209,7 +1265,7 @@ mod tests {
 
     #[test]
     fn test_adc_indirect_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.x = 0x01;
@@ -1228,7 +1284,7 @@ mod tests {
 
     #[test]
     fn test_adc_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x01;
         cpu.registers.y = 0x01;
@@ -1249,7 +1305,7 @@ mod tests {
 
     #[test]
     fn test_sbc_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1265,7 +1321,7 @@ mod tests {
 
     #[test]
     fn test_sbc_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1282,7 +1338,7 @@ mod tests {
 
     #[test]
     fn test_sbc_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1300,7 +1356,7 @@ mod tests {
 
     #[test]
     fn test_sbc_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1318,7 +1374,7 @@ mod tests {
 
     #[test]
     fn test_sbc_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1337,7 +1393,7 @@ mod tests {
 
     #[test]
     fn test_sbc_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1356,7 +1412,7 @@ mod tests {
 
     #[test]
     fn test_sbc_indirect_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1376,7 +1432,7 @@ mod tests {
 
     #[test]
     fn test_sbc_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x02;
@@ -1398,7 +1454,7 @@ mod tests {
 
     #[test]
     fn test_cmp_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1413,7 +1469,7 @@ mod tests {
 
     #[test]
     fn test_cmp_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -1430,7 +1486,7 @@ mod tests {
 
     #[test]
     fn test_cmp_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -1447,7 +1503,7 @@ mod tests {
 
     #[test]
     fn test_cmp_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1464,7 +1520,7 @@ mod tests {
 
     #[test]
     fn test_cmp_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -1482,7 +1538,7 @@ mod tests {
 
     #[test]
     fn test_cmp_absolute_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 0x01;
@@ -1500,7 +1556,7 @@ mod tests {
 
     #[test]
     fn test_cmp_indirect_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.x = 0x01;
@@ -1519,7 +1575,7 @@ mod tests {
 
     #[test]
     fn test_cmp_indirect_y() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0xff;
         cpu.registers.y = 0x01;
@@ -1540,7 +1596,7 @@ mod tests {
 
     #[test]
     fn test_cpx_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1555,7 +1611,7 @@ mod tests {
 
     #[test]
     fn test_cpx_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1571,7 +1627,7 @@ mod tests {
 
     #[test]
     fn test_cpx_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1590,7 +1646,7 @@ mod tests {
 
     #[test]
     fn test_cpy_immediate() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1605,7 +1661,7 @@ mod tests {
 
     #[test]
     fn test_cpy_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1621,7 +1677,7 @@ mod tests {
 
     #[test]
     fn test_cpy_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -1639,7 +1695,7 @@ mod tests {
 
     #[test]
     fn test_inc_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1654,7 +1710,7 @@ mod tests {
 
     #[test]
     fn test_inc_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1670,7 +1726,7 @@ mod tests {
 
     #[test]
     fn test_inc_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1686,7 +1742,7 @@ mod tests {
 
     #[test]
     fn test_inc_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1705,7 +1761,7 @@ mod tests {
 
     #[test]
     fn test_inx() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0xff; // -1
         cpu.registers.program_counter = 0x0100;
@@ -1719,7 +1775,7 @@ mod tests {
 
     #[test]
     fn test_iny() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0xfe; // -2
         cpu.registers.program_counter = 0x0100;
@@ -1735,7 +1791,7 @@ mod tests {
 
     #[test]
     fn test_dec_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1750,7 +1806,7 @@ mod tests {
 
     #[test]
     fn test_dec_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1766,7 +1822,7 @@ mod tests {
 
     #[test]
     fn test_dec_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1782,7 +1838,7 @@ mod tests {
 
     #[test]
     fn test_dec_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1801,7 +1857,7 @@ mod tests {
 
     #[test]
     fn test_dex() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x02;
         cpu.registers.program_counter = 0x0100;
@@ -1818,7 +1874,7 @@ mod tests {
 
     #[test]
     fn test_dey() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.y = 0x02;
         cpu.registers.program_counter = 0x0100;
@@ -1834,7 +1890,7 @@ mod tests {
 
     #[test]
     fn test_asl_accumulator() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x2;
         cpu.registers.program_counter = 0x0100;
@@ -1848,7 +1904,7 @@ mod tests {
 
     #[test]
     fn test_asl_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1863,7 +1919,7 @@ mod tests {
 
     #[test]
     fn test_asl_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1879,7 +1935,7 @@ mod tests {
 
     #[test]
     fn test_asl_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1895,7 +1951,7 @@ mod tests {
 
     #[test]
     fn test_asl_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1914,7 +1970,7 @@ mod tests {
 
     #[test]
     fn test_lsr_accumulator() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.accumulator = 0x2;
         cpu.registers.program_counter = 0x0100;
@@ -1928,7 +1984,7 @@ mod tests {
 
     #[test]
     fn test_lsr_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1943,7 +1999,7 @@ mod tests {
 
     #[test]
     fn test_lsr_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.program_counter = 0x0100;
@@ -1959,7 +2015,7 @@ mod tests {
 
     #[test]
     fn test_lsr_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -1975,7 +2031,7 @@ mod tests {
 
     #[test]
     fn test_lsr_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 1;
         cpu.registers.program_counter = 0x0100;
@@ -1994,7 +2050,7 @@ mod tests {
 
     #[test]
     fn test_rol_accumulator() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x2;
@@ -2009,7 +2065,7 @@ mod tests {
 
     #[test]
     fn test_rol_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2025,7 +2081,7 @@ mod tests {
 
     #[test]
     fn test_rol_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.x = 0x01;
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
@@ -2042,7 +2098,7 @@ mod tests {
 
     #[test]
     fn test_rol_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2058,7 +2114,7 @@ mod tests {
 
     #[test]
     fn test_rol_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.x = 0x01;
@@ -2078,7 +2134,7 @@ mod tests {
 
     #[test]
     fn test_ror_accumulator() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.accumulator = 0x08;
@@ -2093,7 +2149,7 @@ mod tests {
 
     #[test]
     fn test_ror_zero_page() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status = cpu::CARRY_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2109,7 +2165,7 @@ mod tests {
 
     #[test]
     fn test_ror_zero_page_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.x = 0x01;
@@ -2126,7 +2182,7 @@ mod tests {
 
     #[test]
     fn test_ror_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2143,7 +2199,7 @@ mod tests {
 
     #[test]
     fn test_ror_absolute_x() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.x = 0x01;
@@ -2163,7 +2219,7 @@ mod tests {
 
     #[test]
     fn test_jmp_absolute() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2178,7 +2234,7 @@ mod tests {
 
     #[test]
     fn test_jmp_indirect() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2193,13 +2249,34 @@ mod tests {
         assert!(cpu.registers.program_counter == 0xffff);
     }
 
+    #[test]
+    fn test_jmp_indirect_page_boundary() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.program_counter = 0x0100;
+
+        // the high byte of the target is fetched from $0200, not $0300
+        cpu.memory.store(0x0100, 0x6c);
+        cpu.memory.store(0x0101, 0xff);
+        cpu.memory.store(0x0102, 0x02);
+        cpu.memory.store(0x02ff, 0x34);
+        cpu.memory.store(0x0200, 0x12);
+        cpu.memory.store(0x0300, 0x56);
+
+        let cycles = cpu.execute_instruction();
+
+        assert!(cpu.registers.program_counter == 0x1234);
+        assert!(cycles == 5);
+    }
+
     // JSR
 
     #[test]
     fn test_jsr() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2220,9 +2297,10 @@ mod tests {
 
     #[test]
     fn test_rts() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.program_counter = 0x0100;
         cpu.push_word(0x0102);
@@ -2238,7 +2316,7 @@ mod tests {
 
     #[test]
     fn test_bcc() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2254,7 +2332,7 @@ mod tests {
 
     #[test]
     fn test_bcs() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::CARRY_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2271,7 +2349,7 @@ mod tests {
 
     #[test]
     fn test_beq() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::ZERO_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2288,7 +2366,7 @@ mod tests {
 
     #[test]
     fn test_bmi() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::NEGATIVE_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2305,7 +2383,7 @@ mod tests {
 
     #[test]
     fn test_bne() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2321,7 +2399,7 @@ mod tests {
 
     #[test]
     fn test_bpl() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2337,7 +2415,7 @@ mod tests {
 
     #[test]
     fn test_bvc() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2353,7 +2431,7 @@ mod tests {
 
     #[test]
     fn test_bvs() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.processor_status |= cpu::OVERFLOW_FLAG;
         cpu.registers.program_counter = 0x0100;
@@ -2366,11 +2444,62 @@ mod tests {
         assert!(cpu.registers.program_counter == 0x0104);
     }
 
+    #[test]
+    fn test_branch_backwards() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.program_counter = 0x0110;
+
+        cpu.memory.store(0x0110, 0xd0);
+        cpu.memory.store(0x0111, 0xfc);
+
+        cpu.execute_instruction();
+
+        assert!(cpu.registers.program_counter == 0x010e);
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // not taken
+        cpu.registers.processor_status |= cpu::ZERO_FLAG;
+        cpu.registers.program_counter = 0x0100;
+        cpu.memory.store(0x0100, 0xd0);
+        cpu.memory.store(0x0101, 0x10);
+
+        assert!(cpu.execute_instruction() == 2);
+        assert!(cpu.registers.program_counter == 0x0102);
+
+        // taken, same page
+        cpu.registers.program_counter = 0x0100;
+        cpu.memory.store(0x0100, 0xf0);
+
+        assert!(cpu.execute_instruction() == 3);
+        assert!(cpu.registers.program_counter == 0x0112);
+
+        // taken, crossing into the next page
+        cpu.registers.program_counter = 0x01f0;
+        cpu.memory.store(0x01f0, 0xf0);
+        cpu.memory.store(0x01f1, 0x10);
+
+        assert!(cpu.execute_instruction() == 4);
+        assert!(cpu.registers.program_counter == 0x0202);
+
+        // taken, crossing into the previous page
+        cpu.registers.program_counter = 0x0200;
+        cpu.memory.store(0x0200, 0xf0);
+        cpu.memory.store(0x0201, 0xf0);
+
+        assert!(cpu.execute_instruction() == 4);
+        assert!(cpu.registers.program_counter == 0x01f2);
+    }
+
     // CLC
 
     #[test]
     fn test_clc() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2394,7 +2523,7 @@ mod tests {
 
     #[test]
     fn test_cli() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2418,7 +2547,7 @@ mod tests {
 
     #[test]
     fn test_clv() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2438,11 +2567,27 @@ mod tests {
         assert!(!cpu.registers.get_flag(cpu::OVERFLOW_FLAG));
     }
 
+    // CLD
+
+    #[test]
+    fn test_cld() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.processor_status |= cpu::DECIMAL_FLAG;
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.memory.store(0x0100, 0xd8);
+
+        cpu.execute_instruction();
+
+        assert!(!cpu.registers.get_flag(cpu::DECIMAL_FLAG));
+    }
+
     // SEC
 
     #[test]
     fn test_sec() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2457,7 +2602,7 @@ mod tests {
 
     #[test]
     fn test_sei() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.registers.program_counter = 0x0100;
 
@@ -2468,13 +2613,29 @@ mod tests {
         assert!(cpu.registers.get_flag(cpu::INTERRUPT_FLAG));
     }
 
+    // SED
+
+    #[test]
+    fn test_sed() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.memory.store(0x0100, 0xf8);
+
+        cpu.execute_instruction();
+
+        assert!(cpu.registers.get_flag(cpu::DECIMAL_FLAG));
+    }
+
     // BRK
 
     #[test]
     fn test_brk() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.processor_status = 0xff;
         cpu.registers.processor_status ^= cpu::BREAK_FLAG;
@@ -2491,13 +2652,36 @@ mod tests {
         assert!(cpu.registers.program_counter == 0x01ff);
     }
 
+    #[test]
+    fn test_brk_sets_interrupt_flag() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.processor_status = 0x00;
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.memory.store(0x0100, 0x00);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        let cycles = cpu.execute_instruction();
+
+        assert!(cycles == 7);
+        assert!(cpu.registers.get_flag(cpu::INTERRUPT_FLAG));
+        assert!(cpu.registers.program_counter == 0x0200);
+        assert!(cpu.pop() & cpu::BREAK_FLAG != 0);
+    }
+
     // RTI
 
     #[test]
     fn test_rti() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.program_counter = 0x0100;
         cpu.push_word(0x0102);
@@ -2511,13 +2695,35 @@ mod tests {
         assert!(cpu.registers.program_counter == 0x0102);
     }
 
+    #[test]
+    fn test_rti_ignores_break_flag() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+        cpu.push_word(0x0345);
+        cpu.push(0xff);
+
+        cpu.memory.store(0x0100, 0x40);
+
+        let cycles = cpu.execute_instruction();
+
+        assert!(cycles == 6);
+        assert!(cpu.registers.processor_status == 0xef);
+        assert!(cpu.registers.program_counter == 0x0345);
+        assert!(cpu.registers.stack_pointer == 0xfd);
+    }
+
     // IRQ
 
     #[test]
     fn test_irq() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.processor_status = 0xfb;
         cpu.registers.program_counter = 0x0100;
@@ -2526,21 +2732,251 @@ mod tests {
         cpu.memory.store(0xfffe, 0x40);
         cpu.memory.store(0xffff, 0x01);
 
-        cpu.do_interrupts();
+        assert!(cpu.do_interrupts() == 7);
 
-        assert!(cpu.pop() == 0xfb);
+        // B is only pushed set by BRK/PHP
+        assert!(cpu.pop() == 0xeb);
         assert!(cpu.pop_word() == 0x0100);
         assert!(cpu.registers.program_counter == 0x0140);
-        assert!(!cpu.get_interrupt(Interrupt::Irq))
+        assert!(cpu.registers.get_flag(cpu::INTERRUPT_FLAG));
+        // the line stays asserted until the device releases it
+        assert!(cpu.get_interrupt(Interrupt::Irq))
+    }
+
+    #[test]
+    fn test_irq_level_triggered() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.set_flag(cpu::INTERRUPT_FLAG, false);
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.interrupt(Interrupt::Irq, true);
+        cpu.memory.store(0x0100, 0xea);
+        // handler: RTI
+        cpu.memory.store(0x0200, 0x40);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        assert!(cpu.do_interrupts() == 7);
+        cpu.execute_instruction();
+
+        // RTI restores I, and the line is still held, so the IRQ fires again
+        assert!(cpu.registers.program_counter == 0x0100);
+        assert!(cpu.do_interrupts() == 7);
+        assert!(cpu.registers.program_counter == 0x0200);
+        cpu.execute_instruction();
+
+        cpu.interrupt(Interrupt::Irq, false);
+        assert!(cpu.do_interrupts() == 0);
+    }
+
+    #[test]
+    fn test_irq_shared_line() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.set_flag(cpu::INTERRUPT_FLAG, false);
+        cpu.registers.program_counter = 0x0100;
+
+        // handler: RTI
+        cpu.memory.store(0x0200, 0x40);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        cpu.irq.assert(IrqSource::FrameCounter);
+        cpu.irq.assert(IrqSource::Mapper);
+        assert!(cpu.get_interrupt(Interrupt::Irq));
+
+        assert!(cpu.do_interrupts() == 7);
+        cpu.irq.acknowledge(IrqSource::FrameCounter);
+        cpu.execute_instruction();
+
+        // the mapper still holds the line
+        assert!(cpu.irq.is_asserted_by(IrqSource::Mapper));
+        assert!(!cpu.irq.is_asserted_by(IrqSource::FrameCounter));
+        assert!(cpu.do_interrupts() == 7);
+        cpu.irq.acknowledge(IrqSource::Mapper);
+        cpu.execute_instruction();
+
+        assert!(!cpu.get_interrupt(Interrupt::Irq));
+        assert!(cpu.do_interrupts() == 0);
+    }
+
+    #[test]
+    fn test_irq_external_source() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.irq.assert(IrqSource::Dmc);
+        cpu.interrupt(Interrupt::Irq, true);
+        cpu.interrupt(Interrupt::Irq, false);
+
+        assert!(cpu.get_interrupt(Interrupt::Irq));
+        assert!(cpu.irq.is_asserted_by(IrqSource::Dmc));
+        assert!(!cpu.irq.is_asserted_by(IrqSource::External));
+    }
+
+    #[test]
+    fn test_cli_delays_irq() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.interrupt(Interrupt::Irq, true);
+        // CLI, NOP
+        cpu.memory.store(0x0100, 0x58);
+        cpu.memory.store(0x0101, 0xea);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        cpu.execute_instruction();
+
+        // the poll happened before CLI cleared I, so the NOP runs before the IRQ is taken
+        assert!(cpu.do_interrupts() == 0);
+        cpu.execute_instruction();
+        assert!(cpu.registers.program_counter == 0x0102);
+
+        assert!(cpu.do_interrupts() == 7);
+        assert!(cpu.registers.program_counter == 0x0200);
+        cpu.pop();
+        assert!(cpu.pop_word() == 0x0102);
+    }
+
+    #[test]
+    fn test_sei_delays_irq() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.set_flag(cpu::INTERRUPT_FLAG, false);
+        cpu.registers.program_counter = 0x0100;
+
+        // SEI
+        cpu.memory.store(0x0100, 0x78);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        cpu.execute_instruction();
+        cpu.interrupt(Interrupt::Irq, true);
+
+        // the IRQ still gets in right after SEI, with I set in the pushed status
+        assert!(cpu.do_interrupts() == 7);
+        assert!(cpu.registers.program_counter == 0x0200);
+        assert!(cpu.pop() & cpu::INTERRUPT_FLAG != 0);
+        assert!(cpu.pop_word() == 0x0101);
+    }
+
+    #[test]
+    fn test_plp_delays_irq() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+        cpu.push(0x20);
+
+        cpu.interrupt(Interrupt::Irq, true);
+        // PLP, NOP
+        cpu.memory.store(0x0100, 0x28);
+        cpu.memory.store(0x0101, 0xea);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        cpu.execute_instruction();
+
+        assert!(!cpu.registers.get_flag(cpu::INTERRUPT_FLAG));
+        assert!(cpu.do_interrupts() == 0);
+        cpu.execute_instruction();
+        assert!(cpu.do_interrupts() == 7);
+    }
+
+    #[test]
+    fn test_rti_does_not_delay_irq() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+        cpu.push_word(0x0300);
+        cpu.push(0x20);
+
+        cpu.interrupt(Interrupt::Irq, true);
+        cpu.memory.store(0x0100, 0x40);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        cpu.execute_instruction();
+
+        assert!(cpu.registers.program_counter == 0x0300);
+        assert!(cpu.do_interrupts() == 7);
+    }
+
+    #[test]
+    fn test_irq_masked() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.set_flag(cpu::INTERRUPT_FLAG, true);
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.interrupt(Interrupt::Irq, true);
+        cpu.memory.store(0x0100, 0xea);
+        cpu.memory.store(0xfffe, 0x40);
+        cpu.memory.store(0xffff, 0x01);
+
+        assert!(cpu.do_interrupts() == 0);
+
+        cpu.execute_instruction();
+
+        assert!(cpu.registers.program_counter == 0x0101);
+        assert!(cpu.registers.stack_pointer == 0xfd);
+        assert!(cpu.get_interrupt(Interrupt::Irq));
+    }
+
+    #[test]
+    fn test_interrupt_before_instruction() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.interrupt(Interrupt::Nmi, true);
+        cpu.memory.store(0xfffa, 0x00);
+        cpu.memory.store(0xfffb, 0x02);
+        cpu.memory.store(0x0200, 0xe8);
+
+        // the interrupt sequence takes 7 cycles, followed by the 2 cycle INX at the handler
+        let cycles = cpu.execute_instruction();
+
+        assert!(cycles == 9);
+        assert!(cpu.registers.x == 0x01);
+        assert!(cpu.registers.program_counter == 0x0201);
+        cpu.pop();
+        assert!(cpu.pop_word() == 0x0100);
     }
 
     // NMI
 
     #[test]
     fn test_nmi() {
-        let mut cpu = Cpu::new();
+        let mut cpu = Cpu::new(FlatMemory::new());
 
         cpu.reset();
+        cpu.do_interrupts();
 
         cpu.registers.processor_status = 0xff;
         cpu.registers.program_counter = 0x0100;
@@ -2549,11 +2985,578 @@ mod tests {
         cpu.memory.store(0xfffa, 0x40);
         cpu.memory.store(0xfffb, 0x01);
 
-        cpu.do_interrupts();
+        assert!(cpu.do_interrupts() == 7);
 
-        assert!(cpu.pop() == 0xff);
+        assert!(cpu.pop() == 0xef);
         assert!(cpu.pop_word() == 0x0100);
         assert!(cpu.registers.program_counter == 0x0140);
-        assert!(!cpu.get_interrupt(Interrupt::Nmi));
+        assert!(cpu.get_interrupt(Interrupt::Nmi));
+    }
+
+    #[test]
+    fn test_nmi_edge_triggered() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.memory.store(0xfffa, 0x00);
+        cpu.memory.store(0xfffb, 0x02);
+
+        cpu.interrupt(Interrupt::Nmi, true);
+        assert!(cpu.do_interrupts() == 7);
+
+        // holding the line doesn't fire it again
+        assert!(cpu.do_interrupts() == 0);
+        cpu.interrupt(Interrupt::Nmi, true);
+        assert!(cpu.do_interrupts() == 0);
+
+        cpu.interrupt(Interrupt::Nmi, false);
+        cpu.interrupt(Interrupt::Nmi, true);
+        assert!(cpu.do_interrupts() == 7);
+    }
+
+    #[test]
+    fn test_nmi_before_irq() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.set_flag(cpu::INTERRUPT_FLAG, false);
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.interrupt(Interrupt::Irq, true);
+        cpu.interrupt(Interrupt::Nmi, true);
+        cpu.memory.store(0xfffa, 0x00);
+        cpu.memory.store(0xfffb, 0x03);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        assert!(cpu.do_interrupts() == 7);
+        assert!(cpu.registers.program_counter == 0x0300);
+
+        // the NMI handler runs with I set, so the IRQ waits
+        assert!(cpu.do_interrupts() == 0);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+
+        cpu.memory.store(0x0100, 0x00);
+        cpu.memory.store(0xfffa, 0x00);
+        cpu.memory.store(0xfffb, 0x03);
+        cpu.memory.store(0xfffe, 0x00);
+        cpu.memory.store(0xffff, 0x02);
+
+        // NMI arrives while BRK is pushing the return address
+        cpu.tick();
+        cpu.tick();
+        cpu.interrupt(Interrupt::Nmi, true);
+        while cpu.registers.program_counter != 0x0300 && cpu.registers.program_counter != 0x0200
+        {
+            cpu.tick();
+        }
+
+        assert!(cpu.registers.program_counter == 0x0300);
+        assert!(cpu.pop() & cpu::BREAK_FLAG != 0);
+        assert!(cpu.pop_word() == 0x0102);
+
+        // the NMI was serviced by the hijacked BRK
+        assert!(cpu.do_interrupts() == 0);
+    }
+
+    // KIL
+
+    #[test]
+    fn test_kil_jams() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+        cpu.memory.store(0x0100, 0x02);
+        cpu.memory.store(0x0101, 0xe8);
+
+        assert!(cpu.execute_instruction() == 2);
+        assert!(cpu.state() == CpuState::Jammed {
+            opcode: 0x02,
+            address: 0x0100,
+        });
+
+        // time passes, but nothing is executed
+        assert!(cpu.execute_instruction() == 1);
+        assert!(cpu.is_jammed());
+        assert!(matches!(
+            cpu.step(),
+            Err(Error::CpuJam {
+                opcode: 0x02,
+                address: 0x0100,
+            })
+        ));
+        assert!(cpu.registers.x == 0);
+        assert!(cpu.registers.program_counter == 0x0101);
+    }
+
+    #[test]
+    fn test_kil_ignores_interrupts() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.set_flag(cpu::INTERRUPT_FLAG, false);
+        cpu.registers.program_counter = 0x0100;
+        cpu.memory.store(0x0100, 0x12);
+
+        cpu.execute_instruction();
+        cpu.interrupt(Interrupt::Nmi, true);
+        cpu.interrupt(Interrupt::Irq, true);
+
+        assert!(cpu.do_interrupts() == 0);
+        cpu.execute_instruction();
+        assert!(cpu.is_jammed());
+        assert!(cpu.registers.stack_pointer == 0xfd);
+    }
+
+    #[test]
+    fn test_kil_recovers_on_reset() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.reset();
+        cpu.do_interrupts();
+
+        cpu.registers.program_counter = 0x0100;
+        cpu.memory.store(0x0100, 0xf2);
+        cpu.memory.store(0xfffc, 0x00);
+        cpu.memory.store(0xfffd, 0x02);
+        cpu.memory.store(0x0200, 0xe8);
+
+        cpu.execute_instruction();
+        assert!(cpu.is_jammed());
+
+        // the reset sequence followed by INX
+        cpu.interrupt(Interrupt::Rst, true);
+        assert!(cpu.execute_instruction() == 9);
+        assert!(cpu.state() == CpuState::Running);
+        assert!(cpu.registers.x == 1);
+
+        cpu.registers.program_counter = 0x0100;
+        cpu.execute_instruction();
+        assert!(cpu.is_jammed());
+
+        cpu.reset();
+        assert!(cpu.do_interrupts() == 7);
+        assert!(cpu.state() == CpuState::Running);
+        assert!(cpu.registers.program_counter == 0x0200);
+    }
+
+    #[test]
+    fn test_reset_drops_pending_interrupts() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.memory.store(0xfffc, 0x00);
+        cpu.memory.store(0xfffd, 0x02);
+        cpu.memory.store(0x0200, 0x58);
+        cpu.memory.store(0x0201, 0xe8);
+        cpu.power_on();
+        cpu.do_interrupts();
+
+        // abandons the NMI sequence part way through, and forgets the IRQ
+        cpu.interrupt(Interrupt::Nmi, true);
+        cpu.interrupt(Interrupt::Irq, true);
+        cpu.tick();
+        cpu.reset();
+        assert!(!cpu.get_interrupt(Interrupt::Irq));
+        assert!(cpu.do_interrupts() == 7);
+        assert!(cpu.registers.program_counter == 0x0200);
+
+        // CLI, then INX without an interrupt in between
+        cpu.execute_instruction();
+        cpu.execute_instruction();
+        assert!(cpu.registers.x == 1);
+    }
+
+    // Unofficial opcodes
+
+    /// Runs a single instruction from $0200 and returns the number of cycles it took
+    fn execute(cpu: &mut Cpu<FlatMemory>, bytes: &[u8]) -> u8 {
+        for (i, byte) in bytes.iter().enumerate() {
+            cpu.memory.store(0x0200 + i as u16, *byte);
+        }
+        cpu.registers.program_counter = 0x0200;
+
+        cpu.execute_instruction()
+    }
+
+    #[test]
+    fn test_slo() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0x02;
+        cpu.memory.store(0x0010, 0x81);
+
+        assert!(execute(&mut cpu, &[0x07, 0x10]) == 5);
+        assert!(cpu.memory.load(0x0010) == 0x02);
+        assert!(cpu.registers.accumulator == 0x02);
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(!cpu.registers.get_flag(cpu::ZERO_FLAG));
+        assert!(!cpu.registers.get_flag(cpu::NEGATIVE_FLAG));
+    }
+
+    #[test]
+    fn test_rla() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0xff;
+        cpu.registers.processor_status |= cpu::CARRY_FLAG;
+        cpu.memory.store(0x0010, 0x40);
+
+        assert!(execute(&mut cpu, &[0x27, 0x10]) == 5);
+        assert!(cpu.memory.load(0x0010) == 0x81);
+        assert!(cpu.registers.accumulator == 0x81);
+        assert!(!cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(cpu.registers.get_flag(cpu::NEGATIVE_FLAG));
+    }
+
+    #[test]
+    fn test_sre() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0x01;
+        cpu.memory.store(0x0010, 0x03);
+
+        assert!(execute(&mut cpu, &[0x47, 0x10]) == 5);
+        assert!(cpu.memory.load(0x0010) == 0x01);
+        assert!(cpu.registers.accumulator == 0x00);
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(cpu.registers.get_flag(cpu::ZERO_FLAG));
+    }
+
+    #[test]
+    fn test_rra() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // the carry rotated out goes into the addition
+        cpu.registers.accumulator = 0x10;
+        cpu.memory.store(0x0010, 0x03);
+
+        assert!(execute(&mut cpu, &[0x67, 0x10]) == 5);
+        assert!(cpu.memory.load(0x0010) == 0x01);
+        assert!(cpu.registers.accumulator == 0x12);
+        assert!(!cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(!cpu.registers.get_flag(cpu::OVERFLOW_FLAG));
+    }
+
+    #[test]
+    fn test_dcp() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0x42;
+        cpu.memory.store(0x0010, 0x43);
+
+        assert!(execute(&mut cpu, &[0xc7, 0x10]) == 5);
+        assert!(cpu.memory.load(0x0010) == 0x42);
+        assert!(cpu.registers.accumulator == 0x42);
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(cpu.registers.get_flag(cpu::ZERO_FLAG));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_isc() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0x20;
+        cpu.registers.processor_status |= cpu::CARRY_FLAG;
+        cpu.memory.store(0x0010, 0x0f);
+
+        assert!(execute(&mut cpu, &[0xe7, 0x10]) == 5);
+        assert!(cpu.memory.load(0x0010) == 0x10);
+        assert!(cpu.registers.accumulator == 0x10);
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(!cpu.registers.get_flag(cpu::ZERO_FLAG));
+    }
+
+    #[test]
+    fn test_rmw_addressing_modes() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // indexed modes always take the extra cycle, page crossed or not
+        cpu.registers.x = 0x01;
+        cpu.registers.y = 0x01;
+        cpu.memory.store(0x0020, 0x00);
+        cpu.memory.store(0x0021, 0x03);
+        cpu.memory.store(0x0011, 0x00);
+        cpu.memory.store(0x0012, 0x04);
+        let cases: [(&[u8], u8, u16); 6] = [
+            (&[0x17, 0x10], 6, 0x0011),
+            (&[0x0f, 0x00, 0x03], 6, 0x0300),
+            (&[0x1f, 0x00, 0x03], 7, 0x0301),
+            (&[0x1b, 0x00, 0x03], 7, 0x0301),
+            (&[0x03, 0x1f], 8, 0x0300),
+            (&[0x13, 0x20], 8, 0x0301),
+        ];
+        for &(bytes, cycles, address) in &cases {
+            cpu.memory.store(address, 0x01);
+            assert!(execute(&mut cpu, bytes) == cycles);
+            assert!(cpu.memory.load(address) == 0x02);
+        }
+    }
+
+    #[test]
+    fn test_sax() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0xf0;
+        cpu.registers.x = 0x3c;
+        cpu.registers.y = 0x02;
+        cpu.registers.processor_status = 0x24;
+
+        assert!(execute(&mut cpu, &[0x87, 0x10]) == 3);
+        assert!(cpu.memory.load(0x0010) == 0x30);
+        // indexed by Y, unlike the other zero page stores
+        assert!(execute(&mut cpu, &[0x97, 0x10]) == 4);
+        assert!(cpu.memory.load(0x0012) == 0x30);
+        assert!(execute(&mut cpu, &[0x8f, 0x00, 0x03]) == 4);
+        assert!(cpu.memory.load(0x0300) == 0x30);
+        assert!(cpu.registers.processor_status == 0x24);
+    }
+
+    #[test]
+    fn test_lax() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.memory.store(0x0010, 0x80);
+        assert!(execute(&mut cpu, &[0xa7, 0x10]) == 3);
+        assert!(cpu.registers.accumulator == 0x80);
+        assert!(cpu.registers.x == 0x80);
+        assert!(cpu.registers.get_flag(cpu::NEGATIVE_FLAG));
+
+        // a page crossing read takes a cycle more
+        cpu.registers.y = 0x01;
+        cpu.memory.store(0x0300, 0x00);
+        assert!(execute(&mut cpu, &[0xbf, 0xff, 0x02]) == 5);
+        assert!(cpu.registers.x == 0x00);
+        assert!(cpu.registers.get_flag(cpu::ZERO_FLAG));
+        cpu.memory.store(0x0301, 0x7f);
+        assert!(execute(&mut cpu, &[0xbf, 0x00, 0x03]) == 4);
+        assert!(cpu.registers.x == 0x7f);
+    }
+
+    #[test]
+    fn test_lxa() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // A is ORed with the magic constant first
+        assert!(execute(&mut cpu, &[0xab, 0x0f]) == 2);
+        assert!(cpu.registers.accumulator == 0x0e);
+        assert!(cpu.registers.x == 0x0e);
+    }
+
+    #[test]
+    fn test_anc() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // the negative flag is copied into carry
+        cpu.registers.accumulator = 0x80;
+        assert!(execute(&mut cpu, &[0x0b, 0xff]) == 2);
+        assert!(cpu.registers.accumulator == 0x80);
+        assert!(cpu.registers.get_flag(cpu::NEGATIVE_FLAG));
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+
+        assert!(execute(&mut cpu, &[0x2b, 0x7f]) == 2);
+        assert!(cpu.registers.accumulator == 0x00);
+        assert!(cpu.registers.get_flag(cpu::ZERO_FLAG));
+        assert!(!cpu.registers.get_flag(cpu::CARRY_FLAG));
+    }
+
+    #[test]
+    fn test_alr() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0xff;
+        assert!(execute(&mut cpu, &[0x4b, 0x03]) == 2);
+        assert!(cpu.registers.accumulator == 0x01);
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(!cpu.registers.get_flag(cpu::NEGATIVE_FLAG));
+    }
+
+    #[test]
+    fn test_arr() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // (A & operand, carry in) -> (result, carry from bit 6, overflow from bit 6 ^ bit 5)
+        let cases = [
+            (0xff, true, 0xff, true, false),
+            (0xc0, false, 0x60, true, false),
+            (0x80, false, 0x40, true, true),
+            (0x40, false, 0x20, false, true),
+            (0x01, false, 0x00, false, false),
+        ];
+        for &(value, carry, result, carry_out, overflow) in &cases {
+            cpu.registers.accumulator = 0xff;
+            cpu.registers.set_flag(cpu::CARRY_FLAG, carry);
+            assert!(execute(&mut cpu, &[0x6b, value]) == 2);
+            assert!(cpu.registers.accumulator == result);
+            assert!(cpu.registers.get_flag(cpu::CARRY_FLAG) == carry_out);
+            assert!(cpu.registers.get_flag(cpu::OVERFLOW_FLAG) == overflow);
+            assert!(cpu.registers.get_flag(cpu::NEGATIVE_FLAG) == (result & 0x80 != 0));
+            assert!(cpu.registers.get_flag(cpu::ZERO_FLAG) == (result == 0));
+        }
+    }
+
+    #[test]
+    fn test_axs() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // carry like CMP, the incoming carry doesn't borrow
+        cpu.registers.accumulator = 0xf0;
+        cpu.registers.x = 0x3c;
+        assert!(execute(&mut cpu, &[0xcb, 0x10]) == 2);
+        assert!(cpu.registers.x == 0x20);
+        assert!(cpu.registers.accumulator == 0xf0);
+        assert!(cpu.registers.get_flag(cpu::CARRY_FLAG));
+
+        cpu.registers.x = 0x3c;
+        assert!(execute(&mut cpu, &[0xcb, 0x31]) == 2);
+        assert!(cpu.registers.x == 0xff);
+        assert!(!cpu.registers.get_flag(cpu::CARRY_FLAG));
+        assert!(cpu.registers.get_flag(cpu::NEGATIVE_FLAG));
+    }
+
+    #[test]
+    fn test_xaa() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // (A | magic) & X & operand
+        cpu.registers.x = 0xfc;
+        assert!(execute(&mut cpu, &[0x8b, 0x0f]) == 2);
+        assert!(cpu.registers.accumulator == 0x0c);
+        assert!(!cpu.registers.get_flag(cpu::ZERO_FLAG));
+
+        cpu.registers.x = 0x11;
+        assert!(execute(&mut cpu, &[0x8b, 0xff]) == 2);
+        assert!(cpu.registers.accumulator == 0x00);
+        assert!(cpu.registers.get_flag(cpu::ZERO_FLAG));
+    }
+
+    #[test]
+    fn test_las() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.stack_pointer = 0xf3;
+        cpu.registers.y = 0x01;
+        cpu.memory.store(0x0301, 0x3f);
+
+        assert!(execute(&mut cpu, &[0xbb, 0x00, 0x03]) == 4);
+        assert!(cpu.registers.accumulator == 0x33);
+        assert!(cpu.registers.x == 0x33);
+        assert!(cpu.registers.stack_pointer == 0x33);
+        // crossing a page takes a cycle more
+        assert!(execute(&mut cpu, &[0xbb, 0xff, 0x02]) == 5);
+    }
+
+    #[test]
+    fn test_ahx() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        // A & X & (high byte of the base + 1)
+        cpu.registers.accumulator = 0xff;
+        cpu.registers.x = 0xff;
+        cpu.registers.y = 0x01;
+        assert!(execute(&mut cpu, &[0x9f, 0x00, 0x04]) == 5);
+        assert!(cpu.memory.load(0x0401) == 0x05);
+
+        cpu.memory.store(0x0010, 0x80);
+        cpu.memory.store(0x0011, 0x04);
+        assert!(execute(&mut cpu, &[0x93, 0x10]) == 6);
+        assert!(cpu.memory.load(0x0481) == 0x05);
+
+        // crossing a page, the value also becomes the high byte of the address
+        cpu.registers.x = 0x01;
+        assert!(execute(&mut cpu, &[0x9f, 0xff, 0x04]) == 5);
+        assert!(cpu.memory.load(0x0100) == 0x01);
+        assert!(cpu.memory.load(0x0500) == 0x00);
+    }
+
+    #[test]
+    fn test_tas() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.accumulator = 0xff;
+        cpu.registers.x = 0xf3;
+        cpu.registers.y = 0x01;
+        cpu.registers.processor_status = 0x24;
+
+        assert!(execute(&mut cpu, &[0x9b, 0x00, 0x04]) == 5);
+        assert!(cpu.registers.stack_pointer == 0xf3);
+        assert!(cpu.memory.load(0x0401) == 0x01);
+        assert!(cpu.registers.processor_status == 0x24);
+    }
+
+    #[test]
+    fn test_shy() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.y = 0xff;
+        cpu.registers.x = 0x01;
+        assert!(execute(&mut cpu, &[0x9c, 0x00, 0x04]) == 5);
+        assert!(cpu.memory.load(0x0401) == 0x05);
+
+        // crossing a page, the value also becomes the high byte of the address
+        cpu.registers.y = 0x01;
+        cpu.registers.x = 0x02;
+        assert!(execute(&mut cpu, &[0x9c, 0xff, 0x04]) == 5);
+        assert!(cpu.memory.load(0x0101) == 0x01);
+        assert!(cpu.memory.load(0x0501) == 0x00);
+    }
+
+    #[test]
+    fn test_shx() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.x = 0xff;
+        cpu.registers.y = 0x01;
+        assert!(execute(&mut cpu, &[0x9e, 0x00, 0x04]) == 5);
+        assert!(cpu.memory.load(0x0401) == 0x05);
+
+        // crossing a page, the value also becomes the high byte of the address
+        cpu.registers.x = 0x01;
+        cpu.registers.y = 0x02;
+        assert!(execute(&mut cpu, &[0x9e, 0xff, 0x04]) == 5);
+        assert!(cpu.memory.load(0x0101) == 0x01);
+        assert!(cpu.memory.load(0x0501) == 0x00);
+    }
+
+    #[test]
+    fn test_unofficial_nops() {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.registers.x = 0x01;
+        cpu.registers.processor_status = 0x24;
+        let cases: [(&[u8], u8); 7] = [
+            (&[0x1a], 2),
+            (&[0x80, 0xff], 2),
+            (&[0x04, 0x10], 3),
+            (&[0x14, 0x10], 4),
+            (&[0x0c, 0x00, 0x03], 4),
+            (&[0x1c, 0x00, 0x03], 4),
+            (&[0x1c, 0xff, 0x03], 5),
+        ];
+        for &(bytes, cycles) in &cases {
+            assert!(execute(&mut cpu, bytes) == cycles);
+            assert!(cpu.registers.program_counter == 0x0200 + bytes.len() as u16);
+        }
+        assert!(cpu.registers.accumulator == 0);
+        assert!(cpu.registers.processor_status == 0x24);
+    }
+}
@@ -0,0 +1,268 @@
+#[cfg(test)]
+mod tests {
+    use nes::asm;
+    use nes::cpu::Cpu;
+    use nes::mapper::{create_mapper, Mapper, Mirroring};
+    use nes::memory::{Memory, NesMemory};
+    use nes::rom::Rom;
+
+    use std::io::Cursor;
+
+    /// MMC1 cartridge with 4 PRG-ROM banks, each filled with its bank number, and 8K of CHR-RAM
+    fn sxrom() -> Box<dyn Mapper> {
+        sxrom_with_submapper(0)
+    }
+
+    fn sxrom_with_submapper(submapper: u8) -> Box<dyn Mapper> {
+        let mut image =
+            vec![b'N', b'E', b'S', 0x1a, 4, 0, 0x10, 0x08, submapper << 4, 0, 0x07, 0x07];
+        image.resize(16, 0);
+        for bank in 0..4 {
+            image.extend(vec![bank; 16384]);
+        }
+
+        create_mapper(Box::new(Rom::load(&mut Cursor::new(image)).unwrap())).unwrap()
+    }
+
+    /// MMC1 cartridge with 16K of CHR-ROM, each 4K filled with its bank number
+    fn sxrom_with_chr_rom() -> Box<dyn Mapper> {
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 2, 2, 0x10, 0];
+        image.resize(16 + 32768, 0);
+        for bank in 0..4 {
+            image.extend(vec![bank; 4096]);
+        }
+
+        create_mapper(Box::new(Rom::load(&mut Cursor::new(image)).unwrap())).unwrap()
+    }
+
+    /// Loads an MMC1 register through its serial port, with a bus access between writes as
+    /// writes on consecutive cycles are ignored
+    fn write_register(mapper: &mut Box<dyn Mapper>, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.bus_access();
+            mapper.bus_access();
+            mapper.prg_store(address, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn test_sxrom_prg_banks() {
+        let mut mapper = sxrom();
+
+        // the last bank is fixed at $C000 on power on
+        assert!(mapper.prg_load(0xc000) == Some(3));
+        write_register(&mut mapper, 0xe000, 2);
+        assert!(mapper.prg_load(0x8000) == Some(2));
+        assert!(mapper.prg_load(0xffff) == Some(3));
+    }
+
+    #[test]
+    fn test_sxrom_reset_clears_shift_register() {
+        let mut mapper = sxrom();
+
+        // a half finished write
+        write_register(&mut mapper, 0xe000, 1);
+        mapper.reset();
+
+        write_register(&mut mapper, 0xe000, 2);
+        assert!(mapper.prg_load(0x8000) == Some(2));
+    }
+
+    #[test]
+    fn test_sxrom_power_on() {
+        let mut mapper = sxrom();
+
+        // 32K mode, then switch to banks 2/3
+        write_register(&mut mapper, 0x8000, 0);
+        write_register(&mut mapper, 0xe000, 2);
+        assert!(mapper.prg_load(0x8000) == Some(2));
+
+        // reset only restores the fixed last bank, power on forgets the selected bank too
+        mapper.reset();
+        assert!(mapper.prg_load(0x8000) == Some(2));
+        mapper.power_on();
+        assert!(mapper.prg_load(0x8000) == Some(0));
+        assert!(mapper.prg_load(0xc000) == Some(3));
+    }
+
+    #[test]
+    fn test_sxrom_prg_ram() {
+        let mut mapper = sxrom();
+
+        mapper.prg_store(0x6000, 0x12);
+        mapper.prg_store(0x7fff, 0x34);
+        assert!(mapper.prg_load(0x6000) == Some(0x12));
+        assert!(mapper.prg_ram()[0x1fff] == 0x34);
+
+        // disabled PRG-RAM leaves the bus open and ignores writes
+        write_register(&mut mapper, 0xe000, 0x10);
+        assert!(mapper.prg_load(0x6000).is_none());
+        mapper.prg_store(0x6000, 0x56);
+        write_register(&mut mapper, 0xe000, 0);
+        assert!(mapper.prg_load(0x6000) == Some(0x12));
+    }
+
+    #[test]
+    fn test_sxrom_ignores_consecutive_writes() {
+        let mut mapper = sxrom();
+
+        // INC $8000 over $FF writes $FF, then $00 on the next cycle: only the reset counts
+        mapper.bus_access();
+        mapper.prg_store(0x8000, 0xff);
+        mapper.bus_access();
+        mapper.prg_store(0x8000, 0x00);
+
+        // so it still takes all five writes to load a register
+        write_register(&mut mapper, 0x8000, 0x0e);
+        assert!(mapper.mirroring() == Mirroring::Vertical);
+    }
+
+    #[test]
+    fn test_sxrom_on_cpu_without_console() {
+        // the write timing comes from the bus, so a bare CPU on the NES bus can load registers
+        let program = asm::assemble(
+            "
+            .org $c000
+            reset:  LDA #$12
+                    STA $6000
+                    LDA #0
+                    STA $e000
+                    STA $e000
+                    STA $e000
+                    STA $e000
+                    LDA #1
+                    STA $e000
+            loop:   JMP loop
+            .org $fffc
+            .word reset
+            ",
+        )
+        .unwrap();
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 4, 0, 0x10, 0x08, 0, 0, 0x07, 0x07];
+        image.resize(16 + 3 * 16384, 0);
+        let mut last_bank = vec![0; 16384];
+        for segment in &program.segments {
+            let start = segment.origin as usize & 0x3fff;
+            last_bank[start..start + segment.bytes.len()].copy_from_slice(&segment.bytes);
+        }
+        image.extend(last_bank);
+
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+        let mut cpu = Cpu::new(NesMemory::new(Box::new(rom)).unwrap());
+        cpu.memory.power_on();
+        cpu.power_on();
+        for _ in 0..12 {
+            cpu.execute_instruction();
+        }
+
+        assert!(cpu.memory.mapper().prg_ram()[0] == 0x12);
+        assert!(cpu.memory.mapper().prg_load(0x6000).is_none());
+    }
+
+    #[test]
+    fn test_sxrom_fixed_prg() {
+        // SEROM and friends only have 32K and ignore the PRG bank register
+        let mut mapper = sxrom_with_submapper(5);
+
+        write_register(&mut mapper, 0xe000, 2);
+        assert!(mapper.prg_load(0x8000) == Some(0));
+        assert!(mapper.prg_load(0xc000) == Some(1));
+    }
+
+    #[test]
+    fn test_sxrom_fixed_prg_16k() {
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 1, 0, 0x10, 0x08, 5 << 4, 0, 0x07];
+        image.resize(16, 0);
+        image.extend(vec![0x42; 16384]);
+        let mapper = create_mapper(Box::new(Rom::load(&mut Cursor::new(image)).unwrap())).unwrap();
+
+        assert!(mapper.prg_load(0x8000) == Some(0x42));
+        assert!(mapper.prg_load(0xffff) == Some(0x42));
+    }
+
+    #[test]
+    fn test_sxrom_prg_bank_ignores_ram_disable() {
+        let mut mapper = sxrom();
+
+        write_register(&mut mapper, 0xe000, 0x12);
+        assert!(mapper.prg_load(0x8000) == Some(2));
+        assert!(mapper.prg_load(0x6000).is_none());
+    }
+
+    #[test]
+    fn test_sxrom_mirroring() {
+        let mut mapper = sxrom();
+
+        assert!(mapper.mirroring() == Mirroring::OneScreenLower);
+        write_register(&mut mapper, 0x8000, 0x0e);
+        assert!(mapper.mirroring() == Mirroring::Vertical);
+        write_register(&mut mapper, 0x8000, 0x0f);
+        assert!(mapper.mirroring() == Mirroring::Horizontal);
+        write_register(&mut mapper, 0x8000, 0x0d);
+        assert!(mapper.mirroring() == Mirroring::OneScreenUpper);
+    }
+
+    #[test]
+    fn test_sxrom_chr_banks() {
+        let mut mapper = sxrom_with_chr_rom();
+
+        // 8K mode ignores the lowest bank bit
+        write_register(&mut mapper, 0xa000, 3);
+        assert!(mapper.chr_load(0x0000) == 2);
+        assert!(mapper.chr_load(0x1fff) == 3);
+
+        // 4K mode switches each half on its own
+        write_register(&mut mapper, 0x8000, 0x1c);
+        write_register(&mut mapper, 0xc000, 1);
+        assert!(mapper.chr_load(0x0000) == 3);
+        assert!(mapper.chr_load(0x1000) == 1);
+
+        // can't write to CHR-ROM
+        mapper.chr_store(0x1000, 0xff);
+        assert!(mapper.chr_load(0x1000) == 1);
+    }
+
+    #[test]
+    fn test_sxrom_chr_ram() {
+        let mut mapper = sxrom();
+
+        // 4K banks over 8K of CHR-RAM, bank 1 in both halves
+        write_register(&mut mapper, 0x8000, 0x1c);
+        write_register(&mut mapper, 0xa000, 1);
+        write_register(&mut mapper, 0xc000, 1);
+        mapper.chr_store(0x0012, 0x34);
+        assert!(mapper.chr_load(0x1012) == 0x34);
+
+        // bank numbers past the end wrap around
+        write_register(&mut mapper, 0xa000, 3);
+        assert!(mapper.chr_load(0x0012) == 0x34);
+    }
+
+    #[test]
+    fn test_nrom_chr_ram() {
+        // iNES without CHR-ROM means 8K of CHR-RAM
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 1, 0];
+        image.resize(16 + 16384, 0);
+        let mut mapper =
+            create_mapper(Box::new(Rom::load(&mut Cursor::new(image)).unwrap())).unwrap();
+
+        mapper.chr_store(0x1fff, 0x56);
+        assert!(mapper.chr_load(0x1fff) == 0x56);
+    }
+
+    #[test]
+    fn test_nametable_offset() {
+        let nametables = [0x2000, 0x2400, 0x2800, 0x2c00];
+        let offsets = |mirroring: Mirroring| -> Vec<usize> {
+            nametables.iter().map(|&addr| mirroring.nametable_offset(addr + 0x12)).collect()
+        };
+
+        assert!(offsets(Mirroring::Vertical) == vec![0x012, 0x412, 0x012, 0x412]);
+        assert!(offsets(Mirroring::Horizontal) == vec![0x012, 0x012, 0x412, 0x412]);
+        assert!(offsets(Mirroring::OneScreenLower) == vec![0x012; 4]);
+        assert!(offsets(Mirroring::OneScreenUpper) == vec![0x412; 4]);
+        assert!(offsets(Mirroring::FourScreen) == vec![0x012, 0x412, 0x812, 0xc12]);
+        // $3000-$3EFF mirrors the nametables
+        assert!(Mirroring::Vertical.nametable_offset(0x3412) == 0x412);
+    }
+}
@@ -1 +1,10 @@
-mod instruction_tests;
\ No newline at end of file
+mod asm_tests;
+mod console_tests;
+mod disasm_tests;
+mod fds_tests;
+mod instruction_tests;
+mod mapper_tests;
+mod rom_tests;
+mod single_step_tests;
+mod trace_tests;
+mod unif_tests;
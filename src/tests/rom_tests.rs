@@ -0,0 +1,226 @@
+#[cfg(test)]
+mod tests {
+    use nes::error::Error;
+    use nes::mapper::{create_mapper, Mirroring};
+    use nes::rom::{ConsoleType, HeaderFormat, Rom, Timing};
+
+    use std::io::Cursor;
+
+    /// iNES image with one bank of PRG-ROM and one of CHR-ROM
+    fn image(mapper: u8) -> Vec<u8> {
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 1, 1, mapper << 4, 0];
+        image.resize(16, 0);
+        image.resize(16 + 16384 + 8192, 0xea);
+        image
+    }
+
+    #[test]
+    fn test_load() {
+        let rom = Rom::load(&mut Cursor::new(image(0))).unwrap();
+
+        assert!(rom.prg.len() == 16384);
+        assert!(rom.chr.len() == 8192);
+        assert!(create_mapper(Box::new(rom)).is_ok());
+    }
+
+    #[test]
+    fn test_load_invalid_header() {
+        let mut image = image(0);
+        image[3] = 0;
+
+        assert!(matches!(Rom::load(&mut Cursor::new(image)), Err(Error::InvalidHeader)));
+    }
+
+    #[test]
+    fn test_load_truncated() {
+        let mut image = image(0);
+        image.truncate(16 + 16384);
+
+        assert!(matches!(Rom::load(&mut Cursor::new(image)), Err(Error::Truncated)));
+        assert!(matches!(Rom::load(&mut Cursor::new(vec![b'N'])), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn test_unsupported_mapper() {
+        let rom = Rom::load(&mut Cursor::new(image(15))).unwrap();
+
+        assert!(matches!(create_mapper(Box::new(rom)), Err(Error::UnsupportedMapper(15))));
+    }
+
+    #[test]
+    fn test_load_trainer() {
+        let mut image = image(0);
+        let prg_and_chr = image.split_off(16);
+        image[6] |= 0x04;
+        image.extend((0..512).map(|i| i as u8));
+        image.extend(prg_and_chr);
+        image[16 + 512] = 0x4c;
+
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+        assert!(rom.trainer.as_ref().map(|trainer| trainer[0x1ff]) == Some(0xff));
+        assert!(rom.prg[0] == 0x4c);
+        assert!(rom.chr.len() == 8192);
+
+        let mut mapper = create_mapper(Box::new(rom)).unwrap();
+        mapper.power_on();
+        assert!(mapper.prg_load(0x7000) == Some(0x00));
+        assert!(mapper.prg_load(0x7001) == Some(0x01));
+        assert!(mapper.prg_load(0x71ff) == Some(0xff));
+        assert!(mapper.prg_load(0x7200) == Some(0x00));
+
+        // a save restored over the trainer survives a power cycle
+        mapper.prg_ram_mut()[0x1000] = 0xaa;
+        mapper.power_on();
+        assert!(mapper.prg_load(0x7000) == Some(0xaa));
+    }
+
+    #[test]
+    fn test_trainer_without_prg_ram() {
+        // a NES 2.0 header without PRG-RAM still gets 8K for the trainer
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 1, 0, 0x04, 0x08];
+        image.resize(16, 0);
+        image.extend(vec![0x12; 512]);
+        image.extend(vec![0; 16384]);
+
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+        assert!(rom.header.prg_ram_bytes() == 0);
+        let mapper = create_mapper(Box::new(rom)).unwrap();
+        assert!(mapper.prg_ram().len() == 8192);
+        assert!(mapper.prg_load(0x7000) == Some(0x12));
+        assert!(mapper.prg_load(0x6fff) == Some(0x00));
+    }
+
+    #[test]
+    fn test_ines_header() {
+        let mut image = image(1);
+        image[7] = 0x01;
+        image[9] = 0x01;
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.header.format() == HeaderFormat::INes);
+        assert!(rom.header.mapper() == 1);
+        assert!(rom.header.submapper() == 0);
+        assert!(rom.header.prg_ram_bytes() == 8192);
+        assert!(rom.header.chr_ram_bytes() == 0);
+        assert!(rom.header.console_type() == ConsoleType::VsSystem);
+        assert!(rom.header.timing() == Timing::Pal);
+    }
+
+    #[test]
+    fn test_nes20_header() {
+        let mut image = image(1);
+        // mapper 0x101 submapper 5, 32K PRG-NVRAM and 8K CHR-RAM, Dendy, extended console 3
+        image[7] = 0x08 | 0x03;
+        image[8] = 0x51;
+        image[10] = 0x90;
+        image[11] = 0x07;
+        image[12] = 0x03;
+        image[13] = 0x03;
+        image[15] = 0x01;
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.header.format() == HeaderFormat::Nes20);
+        assert!(rom.header.mapper() == 0x101);
+        assert!(rom.header.nes_mapper() == 1);
+        assert!(rom.header.submapper() == 5);
+        assert!(rom.header.prg_ram_bytes() == 32768);
+        assert!(rom.header.prg_nvram_bytes() == 32768);
+        assert!(rom.header.chr_ram_bytes() == 8192);
+        assert!(rom.header.chr_nvram_bytes() == 0);
+        assert!(rom.header.timing() == Timing::Dendy);
+        assert!(rom.header.console_type() == ConsoleType::Extended(3));
+        assert!(rom.header.expansion_device() == 1);
+    }
+
+    #[test]
+    fn test_nes20_rom_sizes() {
+        // 0x100 units of PRG-ROM from the MSB nibble, CHR-ROM as 2^13 * 1 bytes
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 0, 0x34, 0, 0x08, 0, 0xf1];
+        image.resize(16 + 0x100 * 16384 + 8192, 0);
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.prg.len() == 0x100 * 16384);
+        assert!(rom.chr.len() == 8192);
+
+        // absurd sizes just read as truncated
+        let mut image = vec![b'N', b'E', b'S', 0x1a, 0xfc, 0, 0, 0x08, 0, 0x0f];
+        image.resize(16, 0);
+        assert!(matches!(Rom::load(&mut Cursor::new(image)), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn test_nes20_prg_ram() {
+        // NES 2.0 can say there is no PRG-RAM at all
+        let mut image = image(0);
+        image[7] = 0x08;
+        let mut mapper = create_mapper(Box::new(Rom::load(&mut Cursor::new(image)).unwrap()))
+            .unwrap();
+
+        mapper.prg_store(0x6000, 0x12);
+        assert!(mapper.prg_load(0x6000).is_none());
+        assert!(mapper.prg_ram().is_empty());
+    }
+
+    #[test]
+    fn test_full_mapper_number() {
+        let mut image = image(2);
+        image[7] = 0x40;
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.header.mapper() == 66);
+        assert!(rom.header.mapper_name() == Some("GxROM"));
+        assert!(rom.header.to_string().contains("Mapper: 66 (GxROM)"));
+        assert!(matches!(create_mapper(Box::new(rom)), Err(Error::UnsupportedMapper(66))));
+    }
+
+    #[test]
+    fn test_diskdude_header() {
+        let mut image = image(1);
+        image[7..16].copy_from_slice(b"DiskDude!");
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.header.format() == HeaderFormat::Archaic);
+        assert!(rom.header.mapper() == 1);
+        assert!(rom.header.console_type() == ConsoleType::Nes);
+        assert!(rom.header.prg_ram_bytes() == 8192);
+        assert!(rom.header.to_string().contains("Mapper: 1 (MMC1)"));
+        assert!(create_mapper(Box::new(rom)).is_ok());
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mirroring = |mapper: u8, flags: u8| {
+            let mut image = image(mapper);
+            image[6] |= flags;
+            let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+            (rom.header.mirroring(), create_mapper(Box::new(rom)).unwrap().mirroring())
+        };
+
+        assert!(mirroring(0, 0x00) == (Mirroring::Horizontal, Mirroring::Horizontal));
+        assert!(mirroring(0, 0x01) == (Mirroring::Vertical, Mirroring::Vertical));
+        assert!(mirroring(0, 0x09) == (Mirroring::FourScreen, Mirroring::FourScreen));
+        assert!(mirroring(1, 0x01) == (Mirroring::MapperControlled, Mirroring::OneScreenLower));
+        assert!(mirroring(1, 0x08) == (Mirroring::FourScreen, Mirroring::FourScreen));
+    }
+
+    #[test]
+    fn test_invalid_prg_rom_size() {
+        let mut empty = image(0);
+        empty[4] = 0;
+        assert!(matches!(Rom::load(&mut Cursor::new(empty)), Err(Error::InvalidPrgRomSize(0))));
+
+        // NES 2.0 can describe 8K of PRG-ROM, which neither NROM nor MMC1 can map
+        for &mapper in &[0, 1] {
+            let mut image = image(mapper);
+            image[4] = 13 << 2;
+            image[7] = 0x08;
+            image[9] = 0x0f;
+            image.truncate(16 + 8192);
+            image.resize(16 + 8192 + 8192, 0);
+            let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+            assert!(rom.prg.len() == 8192);
+            assert!(matches!(create_mapper(Box::new(rom)), Err(Error::InvalidPrgRomSize(8192))));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+/// Runs Tom Harte's SingleStepTests (https://github.com/SingleStepTests/65x02, the `nes6502`
+/// set) against the CPU. Each vector gives the registers and RAM before and after a single
+/// instruction, plus every bus access made on every cycle in between.
+///
+/// The full suite is large and isn't checked in: point NES_RS_SINGLE_STEP_DIR at a directory of
+/// the `xx.json` files to run it. A handful of vectors in the same format are kept in
+/// fixtures/single_step.json so the harness itself is always exercised.
+#[cfg(test)]
+mod tests {
+    use nes::cpu::Cpu;
+    use nes::memory::{FlatMemory, Memory};
+    use serde_json::Value;
+    use std::env;
+    use std::fmt::Write;
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    const SUITE_DIR_VAR: &str = "NES_RS_SINGLE_STEP_DIR";
+
+    /// KIL halts the CPU, so its vectors only describe the bus of a jammed chip
+    const SKIPPED_OPCODES: [u8; 12] = [
+        0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xb2, 0xd2, 0xf2,
+    ];
+
+    /// Flat RAM that records every bus access as (address, value, is_write)
+    struct RecordingMemory {
+        memory: FlatMemory,
+        accesses: Vec<(u16, u8, bool)>,
+    }
+
+    impl Memory for RecordingMemory {
+        fn power_on(&mut self) {
+            self.memory.power_on();
+            self.accesses.clear();
+        }
+
+        fn reset(&mut self) {
+            self.accesses.clear();
+        }
+
+        fn load(&mut self, address: u16) -> u8 {
+            let value = self.memory.load(address);
+            self.accesses.push((address, value, false));
+            value
+        }
+
+        fn store(&mut self, address: u16, value: u8) {
+            self.accesses.push((address, value, true));
+            self.memory.store(address, value);
+        }
+
+        fn peek(&self, address: u16) -> u8 {
+            self.memory.peek(address)
+        }
+    }
+
+    fn field(state: &Value, name: &str) -> u64 {
+        state[name].as_u64().unwrap_or_else(|| panic!("missing field {}", name))
+    }
+
+    fn ram(state: &Value) -> Vec<(u16, u8)> {
+        state["ram"]
+            .as_array()
+            .expect("missing ram")
+            .iter()
+            .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+            .collect()
+    }
+
+    /// Runs a single vector, returning a description of every mismatch
+    fn run_vector(test: &Value) -> Vec<String> {
+        let initial = &test["initial"];
+        let expected = &test["final"];
+
+        let mut cpu = Cpu::new(RecordingMemory {
+            memory: FlatMemory::new(),
+            accesses: Vec::new(),
+        });
+        cpu.registers.program_counter = field(initial, "pc") as u16;
+        cpu.registers.stack_pointer = field(initial, "s") as u8;
+        cpu.registers.accumulator = field(initial, "a") as u8;
+        cpu.registers.x = field(initial, "x") as u8;
+        cpu.registers.y = field(initial, "y") as u8;
+        cpu.registers.processor_status = field(initial, "p") as u8;
+        for (address, value) in ram(initial) {
+            cpu.memory.memory.store(address, value);
+        }
+
+        let cycles = cpu.execute_instruction();
+
+        let mut errors = Vec::new();
+        let registers = [
+            ("pc", cpu.registers.program_counter as u64),
+            ("s", cpu.registers.stack_pointer as u64),
+            ("a", cpu.registers.accumulator as u64),
+            ("x", cpu.registers.x as u64),
+            ("y", cpu.registers.y as u64),
+            ("p", cpu.registers.processor_status as u64),
+        ];
+        for &(name, actual) in registers.iter() {
+            let wanted = field(expected, name);
+            if actual != wanted {
+                errors.push(format!("{}: expected {:#x}, got {:#x}", name, wanted, actual));
+            }
+        }
+
+        for (address, wanted) in ram(expected) {
+            let actual = cpu.memory.memory.load(address);
+            if actual != wanted {
+                errors.push(format!(
+                    "ram[{:#06x}]: expected {:#04x}, got {:#04x}",
+                    address, wanted, actual
+                ));
+            }
+        }
+
+        let wanted_cycles = test["cycles"].as_array().expect("missing cycles");
+        if cycles as usize != wanted_cycles.len() {
+            errors.push(format!(
+                "cycles: expected {}, got {}",
+                wanted_cycles.len(),
+                cycles
+            ));
+        }
+
+        for (i, wanted) in wanted_cycles.iter().enumerate() {
+            let wanted = (
+                wanted[0].as_u64().unwrap() as u16,
+                wanted[1].as_u64().unwrap() as u8,
+                wanted[2].as_str() == Some("write"),
+            );
+            match cpu.memory.accesses.get(i) {
+                Some(&actual) if actual == wanted => {}
+                actual => errors.push(format!(
+                    "cycle {}: expected {:?}, got {:?}",
+                    i, wanted, actual
+                )),
+            }
+        }
+
+        errors
+    }
+
+    /// Runs every vector in a file, returning a report of the ones that failed
+    fn run_file(path: &Path) -> (usize, String) {
+        let tests: Value = serde_json::from_reader(File::open(path).expect("failed to open vectors"))
+            .expect("failed to parse vectors");
+
+        let mut failures = 0;
+        let mut report = String::new();
+        for test in tests.as_array().expect("expected an array of vectors") {
+            let errors = run_vector(test);
+            if !errors.is_empty() {
+                failures += 1;
+                if failures <= 3 {
+                    let _ = writeln!(report, "  {}: {}", test["name"], errors.join(", "));
+                }
+            }
+        }
+
+        (failures, report)
+    }
+
+    #[test]
+    fn test_single_step_fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/fixtures/single_step.json");
+        let (failures, report) = run_file(&path);
+
+        assert!(failures == 0, "{} vectors failed:\n{}", failures, report);
+    }
+
+    #[test]
+    fn test_single_step_suite() {
+        let dir = match env::var(SUITE_DIR_VAR) {
+            Ok(dir) => dir,
+            Err(_) => {
+                println!("{} not set, skipping SingleStepTests", SUITE_DIR_VAR);
+                return;
+            }
+        };
+
+        let mut report = String::new();
+        for opcode in 0..=255u8 {
+            if SKIPPED_OPCODES.contains(&opcode) {
+                continue;
+            }
+
+            let path = Path::new(&dir).join(format!("{:02x}.json", opcode));
+            if fs::metadata(&path).is_err() {
+                continue;
+            }
+
+            let (failures, file_report) = run_file(&path);
+            if failures != 0 {
+                let _ = writeln!(report, "{:02x}: {} failures\n{}", opcode, failures, file_report);
+            }
+        }
+
+        assert!(report.is_empty(), "SingleStepTests failures:\n{}", report);
+    }
+}
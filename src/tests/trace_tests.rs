@@ -0,0 +1,120 @@
+#[cfg(test)]
+mod tests {
+    use nes::cpu::Cpu;
+    use nes::memory::FlatMemory;
+    use nes::memory::Memory;
+    use nes::trace::Tracer;
+
+    use std::cell::RefCell;
+    use std::io;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    /// Writer the test keeps a handle to, so the trace can be inspected after the cpu ran
+    #[derive(Clone)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Sets up the cpu the way nestest starts in automation mode
+    fn nestest_cpu() -> Cpu<FlatMemory> {
+        let mut cpu = Cpu::new(FlatMemory::new());
+
+        cpu.memory.store(0xfffc, 0x00);
+        cpu.memory.store(0xfffd, 0xc0);
+        cpu.reset();
+        cpu.do_interrupts();
+        cpu.registers.processor_status = 0x24;
+
+        cpu
+    }
+
+    #[test]
+    fn test_trace_nestest_first_line() {
+        let mut cpu = nestest_cpu();
+
+        cpu.memory.store(0xc000, 0x4c);
+        cpu.memory.store(0xc001, 0xf5);
+        cpu.memory.store(0xc002, 0xc5);
+
+        assert!(
+            cpu.trace_line()
+                == "C000  4C F5 C5  JMP $C5F5                       \
+                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+    }
+
+    #[test]
+    fn test_trace_operands() {
+        let mut cpu = nestest_cpu();
+
+        cpu.registers.x = 0x02;
+        cpu.registers.y = 0x10;
+        cpu.memory.store(0x0012, 0x00);
+        cpu.memory.store(0x0013, 0x04);
+        cpu.memory.store(0x0410, 0x5a);
+
+        // LDA ($10,X)
+        cpu.memory.store(0xc000, 0xa1);
+        cpu.memory.store(0xc001, 0x10);
+        assert!(cpu.trace_line().starts_with("C000  A1 10     LDA ($10,X) @ 12 = 0400 = 00 "));
+
+        // LDA ($12),Y
+        cpu.memory.store(0xc001, 0x12);
+        cpu.memory.store(0xc000, 0xb1);
+        assert!(cpu.trace_line().starts_with("C000  B1 12     LDA ($12),Y = 0400 @ 0410 = 5A "));
+
+        // *NOP $0400,X
+        cpu.memory.store(0xc000, 0x1c);
+        cpu.memory.store(0xc002, 0x04);
+        assert!(cpu.trace_line().starts_with("C000  1C 12 04 *NOP $0412,X @ 0414 = 00 "));
+
+        // BNE backwards
+        cpu.memory.store(0xc000, 0xd0);
+        cpu.memory.store(0xc001, 0xfc);
+        assert!(cpu.trace_line().starts_with("C000  D0 FC     BNE $BFFE "));
+
+        // *ISB $12
+        cpu.memory.store(0xc000, 0xe7);
+        cpu.memory.store(0xc001, 0x12);
+        assert!(cpu.trace_line().starts_with("C000  E7 12    *ISB $12 = 00 "));
+    }
+
+    #[test]
+    fn test_tracer_writes_each_instruction() {
+        let mut cpu = nestest_cpu();
+        let buffer = SharedBuffer(Rc::new(RefCell::new(Vec::new())));
+        cpu.tracer = Some(Tracer::new(buffer.clone()));
+
+        // LDX #$05, INX
+        cpu.memory.store(0xc000, 0xa2);
+        cpu.memory.store(0xc001, 0x05);
+        cpu.memory.store(0xc002, 0xe8);
+
+        cpu.execute_instruction();
+        cpu.execute_instruction();
+
+        let trace = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+
+        assert!(lines.len() == 2);
+        assert!(
+            lines[0]
+                == "C000  A2 05     LDX #$05                        \
+                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7"
+        );
+        assert!(
+            lines[1]
+                == "C002  E8        INX                             \
+                    A:00 X:05 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9"
+        );
+    }
+}
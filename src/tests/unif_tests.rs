@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use nes::error::Error;
+    use nes::mapper::{create_mapper, Mirroring};
+    use nes::rom::{Rom, Timing};
+    use nes::unif;
+
+    use std::io::Cursor;
+
+    fn chunk(image: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+        image.extend_from_slice(id);
+        let len = data.len();
+        image.extend((0..4).map(|byte| (len >> (byte * 8)) as u8));
+        image.extend_from_slice(data);
+    }
+
+    /// UNIF image of the given board, with two 16K PRG chunks filled with their number
+    fn image(board: &str) -> Vec<u8> {
+        let mut image = b"UNIF".to_vec();
+        image.extend_from_slice(&[7, 0, 0, 0]);
+        image.resize(32, 0);
+
+        let mut name = board.as_bytes().to_vec();
+        name.push(0);
+        chunk(&mut image, b"MAPR", &name);
+        chunk(&mut image, b"NAME", b"test\0");
+        // chunks are put together in order of their number, not of appearance
+        chunk(&mut image, b"PRG1", &[1; 16384]);
+        chunk(&mut image, b"PRG0", &[0; 16384]);
+        image
+    }
+
+    #[test]
+    fn test_load() {
+        let mut image = image("NES-SNROM");
+        chunk(&mut image, b"MIRR", &[1]);
+        chunk(&mut image, b"BATR", &[1]);
+        chunk(&mut image, b"TVCI", &[1]);
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.board.as_ref().map(|board| &board[..]) == Some("NES-SNROM"));
+        assert!(rom.header.mapper() == 1);
+        assert!(rom.header.battery());
+        assert!(rom.header.timing() == Timing::Pal);
+        assert!(rom.header.prg_rom_bytes() == 32768);
+        assert!(rom.header.chr_ram_bytes() == 8192);
+        assert!(rom.prg[0] == 0 && rom.prg[16384] == 1);
+        assert!(rom.chr.is_empty());
+
+        let mapper = create_mapper(Box::new(rom)).unwrap();
+        assert!(mapper.prg_load(0xc000) == Some(1));
+        assert!(mapper.prg_ram().len() == 8192);
+    }
+
+    #[test]
+    fn test_mirroring() {
+        let mut image = image("NES-NROM-256");
+        chunk(&mut image, b"CHR0", &[0; 8192]);
+        chunk(&mut image, b"MIRR", &[1]);
+        let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+
+        assert!(rom.header.mirroring() == Mirroring::Vertical);
+        assert!(rom.header.chr_rom_bytes() == 8192);
+        assert!(rom.header.chr_ram_bytes() == 0);
+    }
+
+    #[test]
+    fn test_timing() {
+        let timings = [(0, Timing::Ntsc), (1, Timing::Pal), (2, Timing::MultiRegion)];
+        for &(tv, timing) in &timings {
+            let mut image = image("NES-NROM-256");
+            chunk(&mut image, b"TVCI", &[tv]);
+            let rom = Rom::load(&mut Cursor::new(image)).unwrap();
+            assert!(rom.header.timing() == timing);
+        }
+    }
+
+    #[test]
+    fn test_board_mapper() {
+        assert!(unif::board_mapper("NES-NROM-128") == Some((0, 0)));
+        assert!(unif::board_mapper("HVC-SEROM") == Some((1, 5)));
+        assert!(unif::board_mapper("NES-TLROM") == Some((4, 0)));
+        assert!(unif::board_mapper("UNL-Sachen-8259A") == Some((141, 0)));
+        assert!(unif::board_mapper("BMC-GS-2004") == Some((283, 0)));
+        assert!(unif::board_mapper("UNL-NOT-A-BOARD").is_none());
+    }
+
+    #[test]
+    fn test_unsupported_board() {
+        let rom = Rom::load(&mut Cursor::new(image("UNL-NOT-A-BOARD")));
+        assert!(matches!(rom, Err(Error::UnsupportedBoard(ref board)) if board == "UNL-NOT-A-BOARD"
+        ));
+
+        let rom = Rom::load(&mut Cursor::new(image("NES-TLROM"))).unwrap();
+        assert!(matches!(create_mapper(Box::new(rom)), Err(Error::UnsupportedMapper(4))));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut image = image("NES-NROM-256");
+        image.truncate(image.len() - 1);
+        assert!(matches!(Rom::load(&mut Cursor::new(image)), Err(Error::Truncated)));
+
+        let mut image = b"UNIF".to_vec();
+        image.resize(32, 0);
+        assert!(matches!(Rom::load(&mut Cursor::new(image)), Err(Error::InvalidHeader)));
+    }
+
+    #[test]
+    fn test_missing_prg() {
+        let mut image = b"UNIF".to_vec();
+        image.resize(32, 0);
+        chunk(&mut image, b"MAPR", b"NES-NROM-256\0");
+        chunk(&mut image, b"CHR0", &[0; 8192]);
+        assert!(matches!(Rom::load(&mut Cursor::new(image)), Err(Error::InvalidHeader)));
+    }
+}